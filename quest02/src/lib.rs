@@ -47,97 +47,267 @@ fn word_mask<const REVERSE: bool>(needle: &[u8], haystack: &[u8]) -> u64 {
 }
 
 pub fn solve_part2(input: &str) -> u64 {
-    let (first, rest) = input.trim().split_once('\n').unwrap();
+    let (words, grid) = parse_inscription(input);
+    count_symbols(&words, &grid, false, false)
+}
 
-    let words: Vec<&[u8]> = first
-        .strip_prefix("WORDS:")
-        .unwrap()
-        .split(',')
-        .map(|word| word.as_bytes())
+pub fn solve_part3(input: &str) -> u64 {
+    let (words, grid) = parse_inscription(input);
+    count_symbols(&words, &grid, true, true)
+}
+
+/// Count the runic symbols along the rows, optionally wrapping around, and optionally along the columns too, without
+/// keeping track of what covered them.
+fn count_symbols(words: &[String], grid: &[Vec<u8>], wrap: bool, columns: bool) -> u64 {
+    let lexicon = Lexicon::new(words);
+    let mut mask: Vec<Vec<bool>> = grid
+        .par_iter()
+        .map(|row| {
+            let mut marked = vec![false; row.len()];
+            let directions = [Direction::Right, Direction::Left];
+            search_line(
+                &lexicon,
+                |x| row[x],
+                row.len(),
+                wrap,
+                directions,
+                |x, _| marked[x] = true,
+            );
+            marked
+        })
         .collect();
 
-    rest.par_split_ascii_whitespace()
-        .map(|haystack| {
-            let hay_bytes = haystack.as_bytes();
+    if columns {
+        let height = grid.len();
+        let width = grid.iter().map(|row| row.len()).min().unwrap_or(0);
+        let column_masks: Vec<Vec<bool>> = (0..width)
+            .into_par_iter()
+            .map(|x| {
+                let mut marked = vec![false; height];
+                let directions = [Direction::Down, Direction::Up];
+                search_line(
+                    &lexicon,
+                    |y| grid[y][x],
+                    height,
+                    false,
+                    directions,
+                    |y, _| marked[y] = true,
+                );
+                marked
+            })
+            .collect();
+        for (x, column) in column_masks.iter().enumerate() {
+            for (row, &marked) in mask.iter_mut().zip(column) {
+                row[x] |= marked;
+            }
+        }
+    }
 
-            let mask = words
-                .iter()
-                .map(|&word| word_mask::<true>(word, hay_bytes) | word_mask::<false>(word, hay_bytes))
-                .fold(0u64, |a, b| a | b);
+    mask.iter().flatten().filter(|&&marked| marked).count() as u64
+}
 
-            mask.count_ones() as u64
-        })
-        .sum()
+/// The direction in which a word was read when it covered a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Right,
+    Left,
+    Down,
+    Up,
 }
 
-fn transpose<T: Copy>(grid: Vec<Vec<T>>) -> Vec<Vec<T>> {
-    if grid.is_empty() || grid[0].is_empty() {
-        return vec![];
-    }
-    let mut transposed = vec![vec![grid[0][0]; grid.len()]; grid[0].len()];
-    for (y, row) in grid.iter().enumerate() {
-        for (x, &cell) in row.iter().enumerate() {
-            transposed[x][y] = cell;
-        }
-    }
-    transposed
+/// A single word occurrence covering a symbol: the index of the word in the word list and the direction it was read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cover {
+    pub word: usize,
+    pub direction: Direction,
 }
 
-pub fn solve_part3(input: &str) -> u64 {
-    let mut lines = input.trim().lines();
+/// An inscription together with the mask of its runic symbols and, for every marked symbol, which words covered it.
+#[derive(Debug, Clone)]
+pub struct Inscription {
+    pub words: Vec<String>,
+    pub grid: Vec<Vec<u8>>,
+    pub mask: Vec<Vec<bool>>,
+    pub coverage: Vec<Vec<Vec<Cover>>>,
+}
 
-    // Load in words, handling reversal in the word list.
-    let words_line = lines.next().unwrap();
-    let words_iter = words_line.strip_prefix("WORDS:").unwrap().split(',');
-    let mut words = Vec::new();
-    for word in words_iter {
-        let mut word_bytes = word.as_bytes().to_vec();
-        words.push(word_bytes.clone());
-        word_bytes.reverse();
-        words.push(word_bytes);
+impl Inscription {
+    fn new(words: Vec<String>, grid: Vec<Vec<u8>>) -> Self {
+        let mask = grid.iter().map(|row| vec![false; row.len()]).collect();
+        let coverage = grid.iter().map(|row| vec![Vec::new(); row.len()]).collect();
+        Self {
+            words,
+            grid,
+            mask,
+            coverage,
+        }
     }
 
-    // Skip empty line.
-    lines.next();
+    fn mark(&mut self, x: usize, y: usize, cover: Cover) {
+        self.mask[y][x] = true;
+        if !self.coverage[y][x].contains(&cover) {
+            self.coverage[y][x].push(cover);
+        }
+    }
 
-    // Load grid & initialize empty mask.
-    let grid: Vec<Vec<u8>> = lines.map(|row| row.as_bytes().to_vec()).collect();
-    let mut mask: Vec<Vec<bool>> = grid.iter().map(|row| vec![false; row.len()]).collect();
+    /// Search every row for the words in both directions, optionally wrapping around the right edge.
+    fn search_rows(&mut self, wrap: bool) {
+        let lexicon = Lexicon::new(&self.words);
+        let found: Vec<_> = self
+            .grid
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(y, row)| {
+                let mut found = Vec::new();
+                let directions = [Direction::Right, Direction::Left];
+                search_line(
+                    &lexicon,
+                    |x| row[x],
+                    row.len(),
+                    wrap,
+                    directions,
+                    |x, cover| found.push((x, y, cover)),
+                );
+                found
+            })
+            .collect();
+        found.into_iter().for_each(|(x, y, cover)| self.mark(x, y, cover));
+    }
 
-    // Horizontal search.
-    part3_step::<true>(&words, &grid, &mut mask);
+    /// Search every column for the words in both directions, never wrapping.
+    fn search_columns(&mut self) {
+        let lexicon = Lexicon::new(&self.words);
+        let height = self.grid.len();
+        let width = self.grid.iter().map(|row| row.len()).min().unwrap_or(0);
+        let found: Vec<_> = (0..width)
+            .into_par_iter()
+            .flat_map_iter(|x| {
+                let mut found = Vec::new();
+                let directions = [Direction::Down, Direction::Up];
+                search_line(
+                    &lexicon,
+                    |y| self.grid[y][x],
+                    height,
+                    false,
+                    directions,
+                    |y, cover| found.push((x, y, cover)),
+                );
+                found
+            })
+            .collect();
+        found.into_iter().for_each(|(x, y, cover)| self.mark(x, y, cover));
+    }
 
-    // Transpose the grid and mask for vertical search.
-    let grid = transpose(grid);
-    let mut mask = transpose(mask);
+    /// The number of runic symbols, i.e. of marked cells.
+    pub fn count(&self) -> u64 {
+        self.mask.iter().flatten().filter(|&&marked| marked).count() as u64
+    }
 
-    // Vertical search.
-    part3_step::<false>(&words, &grid, &mut mask);
+    /// The words (and directions) that covered the symbol at `(x, y)`.
+    pub fn covers(&self, x: usize, y: usize) -> &[Cover] {
+        &self.coverage[y][x]
+    }
+}
 
-    // Counting time!
-    mask.iter()
-        .map(|row| row.iter().filter(|&&cell| cell).count() as u64)
-        .sum()
+/// The words to look for, indexed by the symbol they start with when read either way, so that most of them can be
+/// ruled out at a glance.
+struct Lexicon<'a> {
+    words: &'a [String],
+    /// For every symbol, the words starting with it when read forwards (`false`) or backwards (`true`).
+    starting_with: Vec<Vec<(usize, bool)>>,
 }
 
-fn part3_step<const PACMAN: bool>(words: &[Vec<u8>], grid: &[Vec<u8>], mask: &mut [Vec<bool>]) {
-    let grid_width = grid[0].len();
+impl<'a> Lexicon<'a> {
+    fn new(words: &'a [String]) -> Self {
+        let mut starting_with = vec![Vec::new(); 256];
+        for (word, needle) in words.iter().map(|w| w.as_bytes()).enumerate() {
+            if let (Some(&first), Some(&last)) = (needle.first(), needle.last()) {
+                starting_with[usize::from(first)].push((word, false));
+                starting_with[usize::from(last)].push((word, true));
+            }
+        }
+        Self { words, starting_with }
+    }
+}
 
-    let xes = |word_len, i| (0..word_len).map(move |j| (j, if PACMAN { (i + j) % grid_width } else { i + j }));
+/// Find the words along a line of `length` symbols, read forwards and backwards, optionally wrapping around its end.
+/// Calls `found` with the position along the line of every symbol covered, along with what covered it.
+fn search_line(
+    lexicon: &Lexicon,
+    symbol: impl Fn(usize) -> u8,
+    length: usize,
+    wrap: bool,
+    [forwards, backwards]: [Direction; 2],
+    mut found: impl FnMut(usize, Cover),
+) {
+    for start in 0..length {
+        for &(word, reversed) in &lexicon.starting_with[usize::from(symbol(start))] {
+            let needle = lexicon.words[word].as_bytes();
+            // Without wrapping the word has to end before the line does. With it, a word can even go around the line
+            // more than once.
+            if !wrap && start + needle.len() > length {
+                continue;
+            }
+            let positions = (start..start + needle.len()).map(|i| if i < length { i } else { i % length });
+            let (matches, direction) = if reversed {
+                let matches = positions.clone().zip(needle.iter().rev()).all(|(i, &c)| symbol(i) == c);
+                (matches, backwards)
+            } else {
+                (positions.clone().zip(needle).all(|(i, &c)| symbol(i) == c), forwards)
+            };
+            if matches {
+                positions.for_each(|i| found(i, Cover { word, direction }));
+            }
+        }
+    }
+}
 
-    grid.par_iter().zip(mask.par_iter_mut()).for_each(|(row, row_mask)| {
-        for word_start in 0..grid_width {
-            let first_char = row[word_start];
-            for word in words.iter().filter(|w| w[0] == first_char) {
-                let matches = xes(word.len(), word_start).all(|(j, x)| row.get(x) == Some(&word[j]));
-                if matches {
-                    for (_, x) in xes(word.len(), word_start) {
-                        row_mask[x] = true;
-                    }
+/// Renders the inscription with runic symbols highlighted using ANSI escapes.
+/// The alternate form (`{:#}`) is plain text, replacing every unmarked symbol with `.`.
+impl std::fmt::Display for Inscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (row, row_mask) in self.grid.iter().zip(&self.mask) {
+            for (&c, &marked) in row.iter().zip(row_mask) {
+                match (marked, f.alternate()) {
+                    (true, false) => write!(f, "\x1b[1;7m{}\x1b[0m", c as char)?,
+                    (true, true) => write!(f, "{}", c as char)?,
+                    (false, false) => write!(f, "{}", c as char)?,
+                    (false, true) => write!(f, ".")?,
                 }
             }
+            writeln!(f)?;
         }
-    });
+        Ok(())
+    }
+}
+
+fn parse_inscription(input: &str) -> (Vec<String>, Vec<Vec<u8>>) {
+    let (first, rest) = input.trim().split_once('\n').unwrap();
+    let words = first
+        .strip_prefix("WORDS:")
+        .unwrap()
+        .split(',')
+        .map(|word| word.to_owned())
+        .collect();
+    let grid = rest.trim().lines().map(|line| line.as_bytes().to_vec()).collect();
+    (words, grid)
+}
+
+/// Like [`solve_part2`], but keeps the marked symbols around instead of just counting them.
+pub fn highlight_part2(input: &str) -> Inscription {
+    let (words, grid) = parse_inscription(input);
+    let mut inscription = Inscription::new(words, grid);
+    inscription.search_rows(false);
+    inscription
+}
+
+/// Like [`solve_part3`], but keeps the marked symbols around instead of just counting them.
+pub fn highlight_part3(input: &str) -> Inscription {
+    let (words, grid) = parse_inscription(input);
+    let mut inscription = Inscription::new(words, grid);
+    inscription.search_rows(true);
+    inscription.search_columns();
+    inscription
 }
 
 #[cfg(test)]
@@ -182,4 +352,35 @@ mod tests {
         let input = "WORDS:ABABAB\n\nAB";
         assert_eq!(solve_part3(input), 2);
     }
+
+    #[test]
+    fn test_highlight() {
+        let input = "WORDS:THE,OWE,MES,ROD,RODEO\n\nHELWORLT\nENIGWDXL\nTRODEOAL";
+        let inscription = highlight_part3(input);
+        assert_eq!(inscription.count(), 10);
+        assert_eq!(format!("{inscription:#}"), "HE..O..T\n....W...\n.RODEO..\n");
+        assert_eq!(
+            inscription.covers(0, 0),
+            [Cover {
+                word: 0,
+                direction: Direction::Right
+            }]
+        );
+        assert_eq!(
+            inscription.covers(4, 1),
+            [Cover {
+                word: 1,
+                direction: Direction::Down
+            }]
+        );
+        assert_eq!(inscription.covers(1, 2).len(), 2);
+    }
+
+    #[test]
+    fn test_inputs() {
+        assert_eq!(solve_part2(include_str!("part2.txt")), 5202);
+        assert_eq!(solve_part3(include_str!("part3.txt")), 11547);
+        assert_eq!(highlight_part2(include_str!("part2.txt")).count(), 5202);
+        assert_eq!(highlight_part3(include_str!("part3.txt")).count(), 11547);
+    }
 }
@@ -1,66 +1,373 @@
+use std::{collections::VecDeque, fmt::Display, io};
+
 use grid::Grid;
 
+const VON_NEUMANN: [(isize, isize); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+const MOORE: [(isize, isize); 8] = [(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+// Hexagonal cells laid out in "odd-r" fashion, i.e. with odd rows shoved half a cell to the right.
+const HEXAGONAL_EVEN_ROW: [(isize, isize); 6] = [(0, -1), (0, 1), (-1, -1), (-1, 0), (1, -1), (1, 0)];
+const HEXAGONAL_ODD_ROW: [(isize, isize); 6] = [(0, -1), (0, 1), (-1, 0), (-1, 1), (1, 0), (1, 1)];
+
+/// Which cells count as the neighbors of a block, as `(dy, dx)` offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// The four orthogonally adjacent cells.
+    VonNeumann,
+    /// The eight cells surrounding a block, diagonals included.
+    Moore,
+    /// The six cells of a hexagonal map where odd rows are shifted half a cell to the right.
+    Hexagonal,
+    /// Arbitrary offsets, which don't need to be symmetric: a block can depend on one that doesn't depend on it.
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Neighborhood {
+    fn offsets(&self, y: usize) -> &[(isize, isize)] {
+        match self {
+            Neighborhood::VonNeumann => &VON_NEUMANN,
+            Neighborhood::Moore => &MOORE,
+            Neighborhood::Hexagonal if y.is_multiple_of(2) => &HEXAGONAL_EVEN_ROW,
+            Neighborhood::Hexagonal => &HEXAGONAL_ODD_ROW,
+            Neighborhood::Custom(offsets) => offsets,
+        }
+    }
+
+    /// The offsets from a block to the blocks it's a neighbor of.
+    fn reversed(&self) -> Neighborhood {
+        match self {
+            Neighborhood::Custom(offsets) => Neighborhood::Custom(offsets.iter().map(|&(dy, dx)| (-dy, -dx)).collect()),
+            // Every block is a neighbor of its own neighbors in the built-in neighborhoods.
+            symmetric => symmetric.clone(),
+        }
+    }
+}
+
+/// What lies beyond the edges of the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// Cells outside the map are at ground level, so blocks on the edge can only be dug once.
+    Ground,
+    /// Cells outside the map are not considered neighbors at all.
+    Ignored,
+}
+
 #[inline]
 pub fn solve(input: &str, include_diagonals: bool) -> u16 {
-    let mut depthmap: Grid<u16> = Grid::new(0, 0);
-    let mut digmap: Grid<bool> = Grid::new(0, 0);
+    // The orthogonal variant ignores neighbors outside the map, while the diagonal one treats them as ground level.
+    if include_diagonals {
+        solve_with(input, &Neighborhood::Moore, Boundary::Ground)
+    } else {
+        solve_with(input, &Neighborhood::VonNeumann, Boundary::Ignored)
+    }
+}
+
+pub fn solve_with(input: &str, neighborhood: &Neighborhood, boundary: Boundary) -> u16 {
+    excavate(input, neighborhood, boundary)
+        .depths
+        .into_vec()
+        .into_iter()
+        .sum()
+}
+
+/// The final state of a dig site, with the depth of every cell.
+#[derive(Debug, Clone)]
+pub struct Excavation {
+    pub depths: Grid<u16>,
+}
+
+// From shallowest to deepest, used to shade the ASCII rendering.
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+impl Excavation {
+    /// The total number of blocks removed.
+    pub fn total(&self) -> u64 {
+        self.depths.iter().map(|&depth| u64::from(depth)).sum()
+    }
+
+    /// The depth of the deepest cell.
+    pub fn max_depth(&self) -> u16 {
+        self.depths.iter().copied().max().unwrap_or(0)
+    }
+
+    /// How many blocks were removed at each level, starting from level 1 at index 0.
+    ///
+    /// A cell dug down to depth `d` contributes a block to each of the levels `1..=d`, so these add up to the total.
+    pub fn level_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; usize::from(self.max_depth()) + 1];
+        for &depth in self.depths.iter() {
+            counts[usize::from(depth)] += 1;
+        }
+        // Turn the histogram of final depths into the number of cells reaching at least each depth.
+        for level in (1..counts.len() - 1).rev() {
+            counts[level] += counts[level + 1];
+        }
+        counts.remove(0);
+        counts
+    }
+
+    /// Write the depths as a binary PGM heatmap, where brighter pixels are deeper.
+    pub fn write_pgm(&self, mut writer: impl io::Write) -> io::Result<()> {
+        let max_depth = u32::from(self.max_depth().max(1));
+        write!(writer, "P5\n{} {}\n255\n", self.depths.cols(), self.depths.rows())?;
+        let pixels: Vec<u8> = self
+            .depths
+            .iter()
+            .map(|&depth| (u32::from(depth) * 255 / max_depth) as u8)
+            .collect();
+        writer.write_all(&pixels)
+    }
+}
+
+/// Renders the dig site as shaded ASCII art, with darker characters for deeper cells.
+impl Display for Excavation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let max_depth = usize::from(self.max_depth().max(1));
+        for row in self.depths.iter_rows() {
+            for &depth in row {
+                let shade = SHADES[usize::from(depth) * (SHADES.len() - 1) / max_depth];
+                write!(f, "{}", shade as char)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
 
+pub fn excavate(input: &str, neighborhood: &Neighborhood, boundary: Boundary) -> Excavation {
+    let mut digmap: Grid<bool> = Grid::new(0, 0);
     for row in input.lines() {
         digmap.push_row(row.bytes().map(|b| b == b'#').collect());
-        depthmap.push_row(row.bytes().map(|_| 0).collect());
     }
 
-    let height = depthmap.rows();
-    let width = depthmap.cols();
-    let mut next_depthmap = depthmap.clone();
+    Excavation {
+        depths: dig(&digmap, neighborhood, boundary),
+    }
+}
 
-    loop {
-        let mut dug = false;
+/// Compute the final depth of every block in one go.
+///
+/// A block keeps being dug as long as all its neighbors are as deep as it is, so its final depth is one more than the
+/// shallowest of its neighbors: that's just the distance to the nearest ground-level cell, which a multi-source BFS
+/// finds in linear time. Blocks that can never reach ground level are left undug.
+fn dig(digmap: &Grid<bool>, neighborhood: &Neighborhood, boundary: Boundary) -> Grid<u16> {
+    let height = digmap.rows();
+    let width = digmap.cols();
+    let mut depthmap: Grid<u16> = Grid::new(height, width);
+    let mut queue = VecDeque::new();
 
-        for (((y, x), &depth), diggable) in depthmap.indexed_iter().zip(digmap.iter_mut()) {
-            if !*diggable {
-                continue;
-            }
+    let neighbor = |(y, x): (usize, usize), (dy, dx): (isize, isize)| {
+        let ny = y.checked_add_signed(dy).filter(|&ny| ny < height)?;
+        let nx = x.checked_add_signed(dx).filter(|&nx| nx < width)?;
+        Some((ny, nx))
+    };
+
+    // Seed the search with every block that borders ground level.
+    for ((y, x), &diggable) in digmap.indexed_iter() {
+        if !diggable {
+            continue;
+        }
+        let at_ground = neighborhood
+            .offsets(y)
+            .iter()
+            .any(|&offset| match neighbor((y, x), offset) {
+                Some(n) => !digmap[n],
+                None => boundary == Boundary::Ground,
+            });
+        if at_ground {
+            depthmap[(y, x)] = 1;
+            queue.push_back((y, x));
+        }
+    }
 
-            let can_dig = if include_diagonals {
-                [
-                    (x != 0).then(|| depthmap[(y, x - 1)]),
-                    (x != width - 1).then(|| depthmap[(y, x + 1)]),
-                    (y != 0).then(|| depthmap[(y - 1, x)]),
-                    (y != height - 1).then(|| depthmap[(y + 1, x)]),
-                    (x != 0 && y != 0).then(|| depthmap[(y - 1, x - 1)]),
-                    (x != width - 1 && y != 0).then(|| depthmap[(y - 1, x + 1)]),
-                    (x != 0 && y != height - 1).then(|| depthmap[(y + 1, x - 1)]),
-                    (x != width - 1 && y != height - 1).then(|| depthmap[(y + 1, x + 1)]),
-                ]
-                .map(|n| n.unwrap_or(0))
-                .into_iter()
-                .all(|h| h == depth)
-            } else {
-                [
-                    (x != 0).then(|| depthmap[(y, x - 1)]),
-                    (x != width - 1).then(|| depthmap[(y, x + 1)]),
-                    (y != 0).then(|| depthmap[(y - 1, x)]),
-                    (y != height - 1).then(|| depthmap[(y + 1, x)]),
-                ]
-                .into_iter()
-                .flatten()
-                .all(|h| h == depth)
-            };
-
-            if can_dig {
-                next_depthmap[(y, x)] = depth + 1;
-                dug = true;
+    // A block's depth only matters to the blocks it's a neighbor of, which aren't its own neighbors in general.
+    let reversed = neighborhood.reversed();
+    while let Some(pos) = queue.pop_front() {
+        let depth = depthmap[pos];
+        for &offset in reversed.offsets(pos.0) {
+            let Some(n) = neighbor(pos, offset) else { continue };
+            if digmap[n] && depthmap[n] == 0 {
+                depthmap[n] = depth + 1;
+                queue.push_back(n);
             }
+        }
+    }
 
-            *diggable = can_dig;
+    depthmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The original round-by-round dig: every block whose neighbors are all as deep as it is gets dug one level
+    /// deeper, until a round goes by without any digging.
+    fn dig_by_rounds(input: &str, neighborhood: &Neighborhood, boundary: Boundary) -> Grid<u16> {
+        let mut digmap: Grid<bool> = Grid::new(0, 0);
+        for row in input.lines() {
+            digmap.push_row(row.bytes().map(|b| b == b'#').collect());
         }
-        depthmap.clone_from(&next_depthmap);
+        let (height, width) = (digmap.rows(), digmap.cols());
+        let mut depthmap: Grid<u16> = Grid::new(height, width);
 
-        if !dug {
-            break;
+        loop {
+            let mut next_depthmap = depthmap.clone();
+            for ((y, x), &depth) in depthmap.indexed_iter() {
+                if !digmap[(y, x)] {
+                    continue;
+                }
+                let can_dig = neighborhood.offsets(y).iter().all(|&(dy, dx)| {
+                    let ny = y.checked_add_signed(dy).filter(|&ny| ny < height);
+                    let nx = x.checked_add_signed(dx).filter(|&nx| nx < width);
+                    match ny.zip(nx) {
+                        Some(n) => depthmap[n] == depth,
+                        None => boundary == Boundary::Ignored || depth == 0,
+                    }
+                });
+                if can_dig {
+                    next_depthmap[(y, x)] = depth + 1;
+                }
+                // Once a block is left behind by its neighbors it never catches up again.
+                digmap[(y, x)] = can_dig;
+            }
+            if next_depthmap == depthmap {
+                return depthmap;
+            }
+            depthmap = next_depthmap;
         }
     }
 
-    depthmap.into_vec().into_iter().sum()
+    #[test]
+    fn test_inputs() {
+        assert_eq!(solve(include_str!("part1.txt"), false), 133);
+        assert_eq!(solve(include_str!("part2.txt"), false), 2749);
+        assert_eq!(solve(include_str!("part3.txt"), true), 10040);
+    }
+
+    #[test]
+    fn test_bfs_matches_rounds() {
+        let inputs = [
+            include_str!("part1.txt"),
+            include_str!("part2.txt"),
+            include_str!("part3.txt"),
+        ];
+        for input in inputs {
+            let neighborhoods = [
+                (Neighborhood::VonNeumann, Boundary::Ground),
+                (Neighborhood::VonNeumann, Boundary::Ignored),
+                (Neighborhood::Moore, Boundary::Ground),
+                (Neighborhood::Moore, Boundary::Ignored),
+                (Neighborhood::Hexagonal, Boundary::Ground),
+                (Neighborhood::Hexagonal, Boundary::Ignored),
+                // Ignoring the edges would leave blocks on the far side with nothing to wait for, so the rounds would
+                // dig them forever.
+                (Neighborhood::Custom(vec![(0, 1)]), Boundary::Ground),
+                (Neighborhood::Custom(vec![(1, -1), (0, 1), (-1, 0)]), Boundary::Ground),
+            ];
+            for (neighborhood, boundary) in neighborhoods {
+                assert_eq!(
+                    excavate(input, &neighborhood, boundary).depths,
+                    dig_by_rounds(input, &neighborhood, boundary),
+                    "{neighborhood:?} with {boundary:?}"
+                );
+            }
+        }
+
+        // Each block only looks to its right, so it's dug once more than the block there.
+        let rightwards = Neighborhood::Custom(vec![(0, 1)]);
+        assert_eq!(
+            excavate("####.", &rightwards, Boundary::Ignored).depths,
+            dig_by_rounds("####.", &rightwards, Boundary::Ignored)
+        );
+        assert_eq!(depths("####.", rightwards, Boundary::Ignored), [[4, 3, 2, 1, 0]]);
+    }
+
+    fn depths(input: &str, neighborhood: Neighborhood, boundary: Boundary) -> Vec<Vec<u16>> {
+        excavate(input, &neighborhood, boundary)
+            .depths
+            .iter_rows()
+            .map(|row| row.copied().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_neighborhoods() {
+        // The only ground is diagonal to the second block of the second row.
+        let input = ".###\n####\n####";
+        assert_eq!(
+            depths(input, Neighborhood::VonNeumann, Boundary::Ignored),
+            [[0, 1, 2, 3], [1, 2, 3, 4], [2, 3, 4, 5]]
+        );
+        assert_eq!(
+            depths(input, Neighborhood::Moore, Boundary::Ignored),
+            [[0, 1, 2, 3], [1, 1, 2, 3], [2, 2, 2, 3]]
+        );
+
+        // The odd row is shifted right, so the hole touches the blocks above and below it and the ones to their right,
+        // but not the corners on the left.
+        let input = "###\n#.#\n###";
+        assert_eq!(
+            depths(input, Neighborhood::Hexagonal, Boundary::Ignored),
+            [[2, 1, 1], [1, 0, 1], [2, 1, 1]]
+        );
+        assert_eq!(
+            depths(input, Neighborhood::VonNeumann, Boundary::Ignored),
+            [[2, 1, 2], [1, 0, 1], [2, 1, 2]]
+        );
+
+        let horizontal = Neighborhood::Custom(vec![(0, -1), (0, 1)]);
+        assert_eq!(
+            depths("#####\n##.##", horizontal, Boundary::Ground),
+            [[1, 2, 3, 2, 1], [1, 1, 0, 1, 1]]
+        );
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let input = "#####\n#####\n#####";
+        assert_eq!(
+            depths(input, Neighborhood::VonNeumann, Boundary::Ground),
+            [[1, 1, 1, 1, 1], [1, 2, 2, 2, 1], [1, 1, 1, 1, 1]]
+        );
+        // Without any ground in sight nothing can be dug.
+        assert_eq!(depths(input, Neighborhood::VonNeumann, Boundary::Ignored), [[0; 5]; 3]);
+        assert_eq!(solve_with(input, &Neighborhood::Moore, Boundary::Ground), 18);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let excavation = excavate(".###\n####\n####", &Neighborhood::Moore, Boundary::Ignored);
+        assert_eq!(excavation.total(), 22);
+        assert_eq!(excavation.max_depth(), 3);
+        assert_eq!(excavation.level_counts(), [11, 8, 3]);
+
+        let excavation = excavate(include_str!("part3.txt"), &Neighborhood::Moore, Boundary::Ground);
+        let level_counts = excavation.level_counts();
+        assert_eq!(level_counts.len(), usize::from(excavation.max_depth()));
+        assert_eq!(level_counts.iter().sum::<usize>() as u64, excavation.total());
+        assert!(level_counts.is_sorted_by(|a, b| a >= b));
+
+        let empty = excavate("", &Neighborhood::VonNeumann, Boundary::Ground);
+        assert_eq!(empty.total(), 0);
+        assert_eq!(empty.level_counts(), []);
+    }
+
+    #[test]
+    fn test_renderings() {
+        let excavation = excavate(".###\n####\n####", &Neighborhood::Moore, Boundary::Ignored);
+        assert_eq!(excavation.to_string(), " -*@\n--*@\n***@\n");
+
+        let mut pgm = Vec::new();
+        excavation.write_pgm(&mut pgm).unwrap();
+        let mut expected = b"P5\n4 3\n255\n".to_vec();
+        expected.extend([0, 85, 170, 255, 85, 85, 170, 255, 170, 170, 170, 255]);
+        assert_eq!(pgm, expected);
+
+        // Nothing dug at all still renders, without dividing by zero.
+        let flat = excavate("..\n..", &Neighborhood::VonNeumann, Boundary::Ground);
+        assert_eq!(flat.to_string(), "  \n  \n");
+        let mut pgm = Vec::new();
+        flat.write_pgm(&mut pgm).unwrap();
+        assert_eq!(pgm, b"P5\n2 2\n255\n\0\0\0\0");
+    }
 }
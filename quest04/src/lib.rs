@@ -1,17 +1,406 @@
-pub fn solve_part12(input: &str) -> u32 {
-    let nails = input.lines().map(|line| line.parse().unwrap()).collect::<Vec<u32>>();
+use std::{
+    fmt::Display,
+    num::ParseIntError,
+    ops::{Bound, Range, RangeBounds},
+};
 
-    let min_nail = nails.iter().min().unwrap();
+/// Everything that can go wrong while levelling nails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A line of the input is not a valid height; `line` is 1-based.
+    Parse { line: usize, source: ParseIntError },
+    /// There are no nails at all.
+    Empty,
+    /// No height within the allowed range can be reached by every nail.
+    Unreachable,
+    /// The number of strikes does not fit in a `u128`.
+    Overflow,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse { line, source } => write!(f, "invalid nail height on line {line}: {source}"),
+            Error::Empty => write!(f, "there are no nails to level"),
+            Error::Unreachable => write!(f, "no allowed height can be reached by every nail"),
+            Error::Overflow => write!(f, "the number of strikes overflows"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// How many strikes it takes to move a nail by one unit in either direction, or `None` if nails can't move that way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Costs {
+    pub raise: Option<u64>,
+    pub lower: Option<u64>,
+}
+
+impl Costs {
+    /// Nails can only be hammered down, one strike per unit.
+    pub const HAMMER_ONLY: Costs = Costs {
+        raise: None,
+        lower: Some(1),
+    };
 
-    nails.iter().map(|nail| nail - min_nail).sum()
+    /// Nails can be pulled up or hammered down, one strike per unit either way.
+    pub const SYMMETRIC: Costs = Costs {
+        raise: Some(1),
+        lower: Some(1),
+    };
 }
 
-pub fn solve_part3(input: &str) -> u32 {
-    let mut nails = input.lines().map(|line| line.parse().unwrap()).collect::<Vec<u32>>();
+/// The cheapest way to level a set of nails: the height they all end at and how many strikes it takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plan {
+    pub target: i64,
+    pub strikes: u128,
+}
 
-    // The median is the element that minimizes the sum of absolute differences, from statistics.
+/// Find the cheapest height to level all nails to, among those within `range`.
+pub fn plan(nails: &[i64], costs: Costs, range: impl RangeBounds<i64>) -> Result<Plan, Error> {
+    let mut nails = nails.to_vec();
     nails.sort_unstable();
-    let median = nails[nails.len() / 2];
+    let (&min_nail, &max_nail) = nails.first().zip(nails.last()).ok_or(Error::Empty)?;
+
+    // Narrow the range down to the heights every nail can actually reach.
+    let mut lo = match range.start_bound() {
+        Bound::Included(&lo) => lo,
+        Bound::Excluded(&lo) => lo.checked_add(1).ok_or(Error::Unreachable)?,
+        Bound::Unbounded => i64::MIN,
+    };
+    let mut hi = match range.end_bound() {
+        Bound::Included(&hi) => hi,
+        Bound::Excluded(&hi) => hi.checked_sub(1).ok_or(Error::Unreachable)?,
+        Bound::Unbounded => i64::MAX,
+    };
+    if costs.raise.is_none() {
+        hi = hi.min(min_nail);
+    }
+    if costs.lower.is_none() {
+        lo = lo.max(max_nail);
+    }
+    if lo > hi {
+        return Err(Error::Unreachable);
+    }
+
+    // Within that range no nail moves in a forbidden direction, so the cost of doing so doesn't matter.
+    let raise = u128::from(costs.raise.unwrap_or(0));
+    let lower = u128::from(costs.lower.unwrap_or(0));
+
+    // The total cost is convex in the target, with slope `raise * below - lower * above`: the weighted median is the
+    // first nail after which that slope stops being negative. Clamping it to the range keeps it optimal.
+    let n = nails.len() as u128;
+    let mut median = max_nail;
+    for (i, &nail) in nails.iter().enumerate() {
+        let below = i as u128 + 1;
+        let raising = raise.checked_mul(below).ok_or(Error::Overflow)?;
+        let lowering = lower.checked_mul(n - below).ok_or(Error::Overflow)?;
+        if raising >= lowering {
+            median = nail;
+            break;
+        }
+    }
+    let target = median.clamp(lo, hi);
+
+    let strikes = nails.iter().try_fold(0u128, |strikes, &nail| {
+        let cost = if nail < target { raise } else { lower };
+        cost.checked_mul(u128::from(nail.abs_diff(target)))
+            .and_then(|cost| strikes.checked_add(cost))
+            .ok_or(Error::Overflow)
+    })?;
+
+    Ok(Plan { target, strikes })
+}
+
+/// Count the strikes needed to hammer every nail down to the shortest one, in a single pass over the nails.
+///
+/// That's the sum of the heights minus the minimum once per nail, so nothing but running totals needs to be kept.
+pub fn strikes_to_minimum(nails: impl IntoIterator<Item = Result<i64, Error>>) -> Result<u128, Error> {
+    let mut count = 0u128;
+    let mut sum = 0i128;
+    let mut min_nail = None;
+
+    for nail in nails {
+        let nail = nail?;
+        count += 1;
+        sum = sum.checked_add(i128::from(nail)).ok_or(Error::Overflow)?;
+        min_nail = Some(min_nail.map_or(nail, |min: i64| min.min(nail)));
+    }
+
+    let min_nail = min_nail.ok_or(Error::Empty)?;
+    let offset = i128::from(min_nail).checked_mul(count as i128).ok_or(Error::Overflow)?;
+    sum.checked_sub(offset)
+        .and_then(|strikes| u128::try_from(strikes).ok())
+        .ok_or(Error::Overflow)
+}
+
+/// A run of consecutive nails (by height) that all get levelled to the same target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// The indices of the nails in this group, once sorted by height.
+    pub nails: Range<usize>,
+    /// The height of the shortest nail in this group.
+    pub lowest: i64,
+    /// The height of the tallest nail in this group.
+    pub highest: i64,
+    pub target: i64,
+    pub strikes: u128,
+}
+
+/// The cheapest way to split the nails into groups, each levelled on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grouping {
+    pub groups: Vec<Group>,
+    pub strikes: u128,
+}
+
+/// Split the nails into at most `k` groups, each levelled to its own height with symmetric unit costs, minimizing the
+/// total number of strikes.
+///
+/// Once sorted every optimal group is a contiguous run of nails levelled to its median, so this is the usual 1-D
+/// k-median dynamic programming over prefixes of the sorted nails, with prefix sums giving each run's cost in O(1).
+/// The fewest groups reaching the optimum are returned.
+pub fn group(nails: &[i64], k: usize) -> Result<Grouping, Error> {
+    let mut nails = nails.to_vec();
+    nails.sort_unstable();
+    let n = nails.len();
+    if n == 0 {
+        return Err(Error::Empty);
+    }
+    if k == 0 {
+        return Err(Error::Unreachable);
+    }
+    let k = k.min(n);
+
+    let prefix: Vec<i128> = std::iter::once(0)
+        .chain(nails.iter().scan(0i128, |sum, &nail| {
+            *sum += i128::from(nail);
+            Some(*sum)
+        }))
+        .collect();
+    let median = |range: &Range<usize>| range.start + (range.end - range.start - 1) / 2;
+    let cost = |range: Range<usize>| {
+        let mid = median(&range);
+        let target = i128::from(nails[mid]);
+        let below = target * (mid - range.start) as i128 - (prefix[mid] - prefix[range.start]);
+        let above = (prefix[range.end] - prefix[mid + 1]) - target * (range.end - mid - 1) as i128;
+        (below + above) as u128
+    };
+
+    // best[g][j] is the cheapest way to level the first `j` nails in `g` groups, with the start of the last group.
+    let mut best = vec![vec![(u128::MAX, 0); n + 1]; k + 1];
+    best[0][0] = (0, 0);
+    for g in 1..=k {
+        for j in g..=n {
+            best[g][j] = (g - 1..j)
+                .filter(|&i| best[g - 1][i].0 != u128::MAX)
+                .map(|i| (best[g - 1][i].0 + cost(i..j), i))
+                .min()
+                .unwrap();
+        }
+    }
+
+    let strikes = (1..=k).map(|g| best[g][n].0).min().unwrap();
+    let mut g = (1..=k).find(|&g| best[g][n].0 == strikes).unwrap();
+    let mut end = n;
+    let mut groups = Vec::with_capacity(g);
+    while g > 0 {
+        let start = best[g][end].1;
+        groups.push(Group {
+            nails: start..end,
+            lowest: nails[start],
+            highest: nails[end - 1],
+            target: nails[median(&(start..end))],
+            strikes: cost(start..end),
+        });
+        end = start;
+        g -= 1;
+    }
+    groups.reverse();
+
+    Ok(Grouping { groups, strikes })
+}
+
+/// Lazily parse the nail heights, one per line.
+pub fn parse(input: &str) -> impl Iterator<Item = Result<i64, Error>> + '_ {
+    input.lines().enumerate().map(|(i, line)| {
+        line.trim()
+            .parse()
+            .map_err(|source| Error::Parse { line: i + 1, source })
+    })
+}
+
+pub fn solve_part12(input: &str) -> u128 {
+    strikes_to_minimum(parse(input)).unwrap()
+}
+
+pub fn solve_part3(input: &str) -> u128 {
+    let nails = parse(input).collect::<Result<Vec<_>, _>>().unwrap();
+    plan(&nails, Costs::SYMMETRIC, ..).unwrap().strikes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let nails = [1, 2, 3, 10];
+        assert_eq!(plan(&nails, Costs::SYMMETRIC, ..), Ok(Plan { target: 2, strikes: 10 }));
+        // Raising is three times as expensive, so it's cheaper to bring everything down to the shortest nail.
+        let costs = Costs {
+            raise: Some(3),
+            lower: Some(1),
+        };
+        assert_eq!(plan(&[0, 10, 20], costs, ..), Ok(Plan { target: 0, strikes: 30 }));
+        assert_eq!(
+            plan(&[5, 3, 8], Costs::HAMMER_ONLY, ..),
+            Ok(Plan { target: 3, strikes: 7 })
+        );
+    }
+
+    #[test]
+    fn test_plan_range() {
+        let nails = [1, 2, 3, 10];
+        assert_eq!(
+            plan(&nails, Costs::SYMMETRIC, 5..=7),
+            Ok(Plan { target: 5, strikes: 14 })
+        );
+        assert_eq!(
+            plan(&nails, Costs::SYMMETRIC, (Bound::Excluded(5), Bound::Included(7))),
+            Ok(Plan { target: 6, strikes: 16 })
+        );
+        assert_eq!(
+            plan(&nails, Costs::SYMMETRIC, ..=-1),
+            Ok(Plan {
+                target: -1,
+                strikes: 20
+            })
+        );
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let stuck = Costs {
+            raise: None,
+            lower: None,
+        };
+        assert_eq!(plan(&[], Costs::SYMMETRIC, ..), Err(Error::Empty));
+        assert_eq!(plan(&[5, 3, 8], Costs::HAMMER_ONLY, 4..), Err(Error::Unreachable));
+        assert_eq!(plan(&[5, 3], stuck, ..), Err(Error::Unreachable));
+        assert_eq!(plan(&[4, 4], stuck, ..), Ok(Plan { target: 4, strikes: 0 }));
+        assert_eq!(plan(&[1], Costs::SYMMETRIC, ..i64::MIN), Err(Error::Unreachable));
+        let expensive = Costs {
+            raise: Some(u64::MAX),
+            lower: Some(u64::MAX),
+        };
+        let nails = [i64::MIN, i64::MIN, i64::MAX, i64::MAX];
+        assert_eq!(plan(&nails, expensive, ..), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_strikes_to_minimum() {
+        assert_eq!(strikes_to_minimum(parse("3\n4\n7\n8")), Ok(10));
+        assert_eq!(strikes_to_minimum(parse(" -3 \n4\n")), Ok(7));
+        assert_eq!(strikes_to_minimum(parse("")), Err(Error::Empty));
+        // The extremes are far apart, but the running totals are wide enough to hold them.
+        let extremes = [i64::MIN, i64::MAX, i64::MIN].map(Ok);
+        assert_eq!(strikes_to_minimum(extremes), Ok(u128::from(u64::MAX)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let source = "x".parse::<i64>().unwrap_err();
+        assert_eq!(
+            strikes_to_minimum(parse("3\nx\n4")),
+            Err(Error::Parse { line: 2, source })
+        );
+        let source = "99999999999999999999".parse::<i64>().unwrap_err();
+        assert_eq!(
+            strikes_to_minimum(parse("1\n2\n99999999999999999999")),
+            Err(Error::Parse { line: 3, source })
+        );
+        // Parsing is lazy, so nothing after the first bad line is looked at.
+        let nails = parse("1\ny").chain(std::iter::repeat_with(|| unreachable!()));
+        assert!(matches!(strikes_to_minimum(nails), Err(Error::Parse { line: 2, .. })));
+    }
+
+    #[test]
+    fn test_group() {
+        let grouping = group(&[12, 1, 11, 3, 10, 2], 2).unwrap();
+        assert_eq!(
+            grouping.groups,
+            vec![
+                Group {
+                    nails: 0..3,
+                    lowest: 1,
+                    highest: 3,
+                    target: 2,
+                    strikes: 2,
+                },
+                Group {
+                    nails: 3..6,
+                    lowest: 10,
+                    highest: 12,
+                    target: 11,
+                    strikes: 2,
+                },
+            ]
+        );
+        assert_eq!(grouping.strikes, 4);
+
+        let nails = [1, 2, 3, 10];
+        let single = group(&nails, 1).unwrap();
+        assert_eq!(single.groups.len(), 1);
+        assert_eq!(single.strikes, plan(&nails, Costs::SYMMETRIC, ..).unwrap().strikes);
+    }
+
+    #[test]
+    fn test_group_boundaries() {
+        assert_eq!(group(&[], 3), Err(Error::Empty));
+        assert_eq!(group(&[1, 2], 0), Err(Error::Unreachable));
+        // Asking for more groups than needed only uses as many as it takes to reach the optimum.
+        let grouping = group(&[5, 9, 5], 10).unwrap();
+        assert_eq!(grouping.strikes, 0);
+        assert_eq!(grouping.groups.len(), 2);
+        assert_eq!(grouping.groups[0].nails, 0..2);
+    }
 
-    nails.iter().map(|nail| nail.abs_diff(median)).sum()
+    #[test]
+    fn test_group_matches_brute_force() {
+        let nails = [7, -3, 4, 4, 20, 13, 0, 9];
+        let n = nails.len();
+        for k in 1..=n {
+            // Every way to cut the sorted nails into at most `k` runs, each levelled on its own.
+            let mut sorted = nails;
+            sorted.sort_unstable();
+            let brute_force = (0u32..1 << (n - 1))
+                .filter(|cuts| cuts.count_ones() < k as u32)
+                .map(|cuts| {
+                    let mut start = 0;
+                    let mut strikes = 0;
+                    for end in 1..=n {
+                        if end == n || cuts & (1 << (end - 1)) != 0 {
+                            strikes += plan(&sorted[start..end], Costs::SYMMETRIC, ..).unwrap().strikes;
+                            start = end;
+                        }
+                    }
+                    strikes
+                })
+                .min()
+                .unwrap();
+            let grouping = group(&nails, k).unwrap();
+            assert_eq!(grouping.strikes, brute_force, "{k} groups");
+            assert_eq!(grouping.groups.iter().map(|g| g.strikes).sum::<u128>(), brute_force);
+        }
+    }
 }
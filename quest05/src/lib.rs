@@ -1,42 +1,204 @@
-use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use std::{
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
 
-type Columns = [Vec<u16>; 4];
+use rustc_hash::{FxHashMap as HashMap, FxHasher};
 
-fn concat(a: usize, b: u16) -> usize {
-    let b = usize::from(b);
-    let mut ten = 10;
-    while ten <= b {
-        ten *= 10;
+type Person = u64;
+
+// Column fingerprints are polynomial hashes modulo the Mersenne prime 2^61 - 1.
+const MODULUS: u64 = (1 << 61) - 1;
+const BASE: u64 = 0x0123_4567_89ab_cdef;
+const BASE_INVERSE: u64 = pow_mod(BASE, MODULUS - 2);
+
+const fn mul_mod(a: u64, b: u64) -> u64 {
+    (a as u128 * b as u128 % MODULUS as u128) as u64
+}
+
+const fn pow_mod(mut base: u64, mut exp: u64) -> u64 {
+    let mut result = 1;
+    while exp != 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base);
+        }
+        base = mul_mod(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// A column of dancers, with O(1) removal at the front and insertion costing O(min(index, len - index)).
+///
+/// Clappers are always inserted around position `clapper % (2 * len)`, which for long columns is close to the front,
+/// so a `VecDeque` keeps insertions cheap without needing anything fancier.
+///
+/// Alongside the people it keeps a fingerprint `sum(person[k] * BASE^k)`, updated in step with every change, so a
+/// state can be remembered without cloning it.
+#[derive(Debug, Clone, Default)]
+struct Column {
+    people: VecDeque<Person>,
+    fingerprint: u64,
+}
+
+impl Column {
+    fn front(&self) -> Option<Person> {
+        self.people.front().copied()
+    }
+
+    fn len(&self) -> usize {
+        self.people.len()
+    }
+
+    fn push_back(&mut self, person: Person) {
+        let term = mul_mod(person % MODULUS, pow_mod(BASE, self.len() as u64));
+        self.fingerprint = (self.fingerprint + term) % MODULUS;
+        self.people.push_back(person);
+    }
+
+    fn pop_front(&mut self) -> Option<Person> {
+        let person = self.people.pop_front()?;
+        // Everyone else moves one place forward, dividing their term by BASE.
+        let rest = (self.fingerprint + MODULUS - person % MODULUS) % MODULUS;
+        self.fingerprint = mul_mod(rest, BASE_INVERSE);
+        Some(person)
+    }
+
+    fn insert(&mut self, index: usize, person: Person) {
+        // Split the fingerprint around `index` by summing whichever side is shorter.
+        let (prefix, suffix) = if index <= self.len() / 2 {
+            let prefix = self
+                .people
+                .range(..index)
+                .rev()
+                .fold(0, |acc, &p| (mul_mod(acc, BASE) + p % MODULUS) % MODULUS);
+            (prefix, (self.fingerprint + MODULUS - prefix) % MODULUS)
+        } else {
+            let suffix = self
+                .people
+                .range(index..)
+                .rev()
+                .fold(0, |acc, &p| (mul_mod(acc, BASE) + p % MODULUS) % MODULUS);
+            let suffix = mul_mod(suffix, pow_mod(BASE, index as u64));
+            ((self.fingerprint + MODULUS - suffix) % MODULUS, suffix)
+        };
+        // Everyone from `index` onwards moves one place back, multiplying their term by BASE.
+        let term = mul_mod(person % MODULUS, pow_mod(BASE, index as u64));
+        self.fingerprint = (prefix + term + mul_mod(suffix, BASE)) % MODULUS;
+        self.people.insert(index, person);
     }
-    a * ten + b
 }
 
-fn shout(columns: &Columns) -> usize {
-    columns.iter().map(|column| column[0]).fold(0, |acc, n| concat(acc, n))
+type Columns = Vec<Column>;
+
+/// A fingerprint of the whole dance, to detect repeated states.
+fn fingerprint(columns: &Columns, i: usize) -> u64 {
+    let mut hasher = FxHasher::default();
+    (i % columns.len()).hash(&mut hasher);
+    columns.iter().for_each(|c| (c.fingerprint, c.len()).hash(&mut hasher));
+    hasher.finish()
+}
+
+/// The number shouted after a round, kept as its decimal digits since it easily outgrows any integer type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Shout(String);
+
+impl Shout {
+    fn new(mut digits: String) -> Self {
+        // Leading zeros would break the ordering, which relies on longer numbers being larger.
+        let zeros = digits.len() - digits.trim_start_matches('0').len();
+        digits.drain(..zeros.min(digits.len().saturating_sub(1)));
+        Self(digits)
+    }
+
+    /// Multiply the shout by `factor`, digit by digit.
+    pub fn times(&self, factor: u64) -> Shout {
+        let mut digits = Vec::with_capacity(self.0.len() + 20);
+        let mut carry = 0u128;
+        for digit in self.0.bytes().rev() {
+            carry += u128::from(digit - b'0') * u128::from(factor);
+            digits.push(b'0' + (carry % 10) as u8);
+            carry /= 10;
+        }
+        while carry != 0 {
+            digits.push(b'0' + (carry % 10) as u8);
+            carry /= 10;
+        }
+        digits.reverse();
+        Shout::new(String::from_utf8(digits).unwrap())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Ord for Shout {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.len().cmp(&other.0.len()).then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl PartialOrd for Shout {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Shout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The people at the front of every column, read left to right. A column whose last person just clapped is empty, and
+/// gets skipped.
+fn shout(columns: &Columns) -> Shout {
+    Shout::new(
+        columns
+            .iter()
+            .filter_map(Column::front)
+            .map(|person| person.to_string())
+            .collect(),
+    )
 }
 
 fn parse_input(input: &str) -> Columns {
-    let mut columns = Columns::default();
-    input
-        .trim()
-        .lines()
-        .map(|line| line.split(' ').map(|n| n.parse::<u16>().unwrap()))
-        .for_each(|row| row.zip(columns.iter_mut()).for_each(|(cell, column)| column.push(cell)));
+    let mut columns = Columns::new();
+    for line in input.trim().lines() {
+        let row = line.split_ascii_whitespace().map(|n| n.parse::<Person>().unwrap());
+        for (x, cell) in row.enumerate() {
+            if x == columns.len() {
+                columns.push(Column::default());
+            }
+            columns[x].push_back(cell);
+        }
+    }
     columns
 }
 
 fn step(columns: &mut Columns, i: usize) {
-    let clapper = columns[i % 4].remove(0);
-    let target_column = &mut columns[(i + 1) % 4];
-    let height = target_column.len();
-    let mut insertion_point = (clapper as usize % (2 * height)).abs_diff(1);
+    let n = columns.len();
+    // The first clapper comes from the first row, and every one after that from the column the previous one joined.
+    let clapper = columns[i % n]
+        .pop_front()
+        .expect("the clapper's column should never be empty");
+    let target_column = &mut columns[(i + 1) % n];
+    let height = target_column.len() as Person;
+    if height == 0 {
+        target_column.insert(0, clapper);
+        return;
+    }
+    let mut insertion_point = (clapper % (2 * height)).abs_diff(1);
     if insertion_point > height {
         insertion_point = 2 * height - insertion_point;
     }
-    target_column.insert(insertion_point, clapper);
+    target_column.insert(insertion_point as usize, clapper);
 }
 
-pub fn solve_part1(input: &str) -> usize {
+pub fn solve_part1(input: &str) -> Shout {
     let mut columns = parse_input(input);
     for i in 0..10 {
         step(&mut columns, i);
@@ -44,34 +206,123 @@ pub fn solve_part1(input: &str) -> usize {
     shout(&columns)
 }
 
-pub fn solve_part2(input: &str) -> usize {
+/// The shape of a dance that eventually repeats itself.
+///
+/// Rounds are numbered from 1, with round `r` shouting the state reached after `r` claps. The dance goes through
+/// `pre_period` states before entering a loop of `cycle_len` states that repeats forever.
+#[derive(Debug, Clone)]
+pub struct Dance {
+    pub pre_period: usize,
+    pub cycle_len: usize,
+    /// The shout of every distinct state, starting with the initial one.
+    shouts: Vec<Shout>,
+    /// For every shout: the rounds it occurs at before the cycle, and its first occurrences once inside it.
+    occurrences: HashMap<Shout, (Vec<u64>, Vec<u64>)>,
+}
+
+impl Dance {
+    /// The shout heard at the given round.
+    pub fn shout_at(&self, round: u64) -> &Shout {
+        let round = round as usize;
+        if round < self.pre_period {
+            &self.shouts[round]
+        } else {
+            &self.shouts[self.pre_period + (round - self.pre_period) % self.cycle_len]
+        }
+    }
+
+    /// Every shout heard within the cycle, in order.
+    pub fn cycle(&self) -> &[Shout] {
+        &self.shouts[self.pre_period..]
+    }
+
+    /// The largest shout ever heard.
+    pub fn max_shout(&self) -> &Shout {
+        // The initial state only counts if the cycle comes back to it.
+        let first = if self.pre_period == 0 { 0 } else { 1 };
+        self.shouts[first..].iter().max().unwrap()
+    }
+
+    /// The round at which `shout` is heard for the `n`th time, counting from 1.
+    pub fn nth_occurrence(&self, shout: &Shout, n: u64) -> Option<u64> {
+        let (before, within) = self.occurrences.get(shout)?;
+        let n = n.checked_sub(1)?;
+        if let Some(&round) = before.get(n as usize) {
+            return Some(round);
+        }
+        if within.is_empty() {
+            return None;
+        }
+        // Past the pre-period the occurrences repeat every cycle.
+        let n = n - before.len() as u64;
+        let laps = n / within.len() as u64;
+        Some(within[(n % within.len() as u64) as usize] + laps * self.cycle_len as u64)
+    }
+
+    /// The first shout to be heard `n` times, along with the round it happens at.
+    pub fn first_to_reach(&self, n: u64) -> Option<(&Shout, u64)> {
+        self.occurrences
+            .keys()
+            .filter_map(|shout| Some((shout, self.nth_occurrence(shout, n)?)))
+            .min_by_key(|&(_, round)| round)
+    }
+}
+
+/// Dance until a state repeats, recording every shout along the way.
+pub fn analyze(input: &str) -> Dance {
+    let mut columns = parse_input(input);
+    let mut seen = HashMap::default();
+    let mut shouts = Vec::new();
+
+    let mut i = 0;
+    let pre_period = loop {
+        if let Some(start) = seen.insert(fingerprint(&columns, i), i) {
+            break start;
+        }
+        shouts.push(shout(&columns));
+        step(&mut columns, i);
+        i += 1;
+    };
+    let cycle_len = shouts.len() - pre_period;
+
+    let mut occurrences: HashMap<Shout, (Vec<u64>, Vec<u64>)> = HashMap::default();
+    for (round, shouted) in shouts.iter().enumerate().skip(1).take(pre_period.saturating_sub(1)) {
+        occurrences.entry(shouted.clone()).or_default().0.push(round as u64);
+    }
+    // The first lap of the cycle starts at round `pre_period`, or one full cycle later when that's the initial state.
+    let first_lap = pre_period.max(1)..pre_period.max(1) + cycle_len;
+    for round in first_lap {
+        let shouted = &shouts[pre_period + (round - pre_period) % cycle_len];
+        occurrences.entry(shouted.clone()).or_default().1.push(round as u64);
+    }
+
+    Dance {
+        pre_period,
+        cycle_len,
+        shouts,
+        occurrences,
+    }
+}
+
+pub fn solve_part2(input: &str) -> Shout {
+    // This dance doesn't repeat for tens of millions of rounds, so it's way quicker to count shouts as they happen
+    // than to go looking for its cycle with `analyze`.
     let mut columns = parse_input(input);
     let mut counters = HashMap::default();
     for i in 0.. {
         step(&mut columns, i);
         let shouted = shout(&columns);
-        let counter = counters.entry(shouted).or_insert(0);
+        let counter = counters.entry(shouted.clone()).or_insert(0);
         *counter += 1;
         if *counter == 2024 {
-            return (i + 1) * shouted;
+            return shouted.times(i as u64 + 1);
         }
     }
     unreachable!()
 }
 
-pub fn solve_part3(input: &str) -> usize {
-    let mut columns = parse_input(input);
-    let mut states = HashSet::default();
-    let mut answer = usize::MIN;
-    for i in 0.. {
-        if !states.insert(columns.clone()) {
-            return answer;
-        }
-        step(&mut columns, i);
-        let shouted = shout(&columns);
-        answer = answer.max(shouted);
-    }
-    unreachable!()
+pub fn solve_part3(input: &str) -> Shout {
+    analyze(input).max_shout().clone()
 }
 
 #[cfg(test)]
@@ -80,6 +331,61 @@ mod tests {
 
     #[test]
     fn test_part3() {
-        assert_eq!(solve_part3(include_str!("part3.txt")), 8641100010001000);
+        assert_eq!(solve_part3(include_str!("part3.txt")).as_str(), "8641100010001000");
+    }
+
+    #[test]
+    fn test_shout() {
+        let big = Shout::new("98765432109876543210".to_owned());
+        assert!(big > Shout::new("99999".to_owned()));
+        assert_eq!(Shout::new("0042".to_owned()).as_str(), "42");
+        assert_eq!(big.times(1000).as_str(), "98765432109876543210000");
+
+        let six_columns = "1 2 3 4 5 6\n7 8 9 10 11 12";
+        assert_eq!(solve_part1(six_columns).as_str(), "723416");
+    }
+
+    #[test]
+    fn test_empty_columns() {
+        // The first column is left empty by the very first clap, and every fourth one after that.
+        assert_eq!(solve_part1("1 2 3 4").as_str(), "214");
+        let dance = analyze("1 2 3 4");
+        assert_eq!((dance.pre_period, dance.cycle_len), (0, 4));
+        assert_eq!(dance.shout_at(1).as_str(), "134");
+        assert_eq!(dance.max_shout().as_str(), "1234");
+    }
+
+    #[test]
+    fn test_dance_analysis() {
+        let input = include_str!("part1.txt");
+        let dance = analyze(input);
+        assert_eq!((dance.pre_period, dance.cycle_len), (361, 4));
+        assert_eq!(dance.shout_at(10), &solve_part1(input));
+
+        // Cross-check against brute force, for shouts heard both before and within the cycle.
+        let mut columns = parse_input(input);
+        let mut counters = HashMap::default();
+        for i in 0..2000 {
+            step(&mut columns, i);
+            let shouted = shout(&columns);
+            let round = i as u64 + 1;
+            assert_eq!(dance.shout_at(round), &shouted);
+            let counter = counters.entry(shouted.clone()).or_insert(0);
+            *counter += 1;
+            assert_eq!(dance.nth_occurrence(&shouted, *counter), Some(round));
+        }
+    }
+
+    #[test]
+    fn test_incremental_fingerprint() {
+        let mut columns = parse_input(include_str!("part2.txt"));
+        for i in 0..1000 {
+            step(&mut columns, i);
+        }
+        for column in &columns {
+            let mut fresh = Column::default();
+            column.people.iter().for_each(|&person| fresh.push_back(person));
+            assert_eq!(column.fingerprint, fresh.fingerprint);
+        }
     }
 }
@@ -1,118 +1,447 @@
-use std::{collections::hash_map::Entry, hash::Hash};
+use std::{collections::BTreeMap, fmt::Write, hash::Hash};
 
-use rustc_hash::FxHashMap as HashMap;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
 
 type Tree<Id> = HashMap<Id, Vec<Id>>;
 
-fn walk<Id: Hash + Eq + Copy, IsFruit: Fn(Id) -> bool>(
-    tree: &Tree<Id>,
-    path: &mut Vec<Id>,
-    paths: &mut HashMap<usize, Vec<Id>>,
-    is_fruit: &IsFruit,
-) {
-    let &conductor = path.last().unwrap();
-    if is_fruit(conductor) {
-        match paths.entry(path.len()) {
-            Entry::Occupied(entry) => {
-                entry.remove();
+/// Everything suspicious about an input that's supposed to describe a tree rooted at `RR`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostics<Id> {
+    /// Groups of nodes that can all reach each other; these are pruned from the tree, along with every edge into them.
+    pub cycles: Vec<Vec<Id>>,
+    /// Nodes whose children are listed on more than one line; only the last listing is kept.
+    pub redefined: Vec<Id>,
+    /// Nodes listed as a child more than once, be it by the same parent or by different ones.
+    pub shared: Vec<Id>,
+    /// Nodes with children of their own that can't be reached from the root.
+    pub unreachable: Vec<Id>,
+}
+
+/// Find the groups of nodes lying on a cycle, via an iterative version of Tarjan's algorithm.
+fn find_cycles<Id: Hash + Eq + Copy>(tree: &Tree<Id>) -> Vec<Vec<Id>> {
+    let mut index = HashMap::default();
+    let mut lowlink = HashMap::default();
+    let mut on_stack = HashSet::default();
+    let mut stack = Vec::new();
+    let mut cycles = Vec::new();
+
+    let children = |node| tree.get(&node).map_or(&[][..], Vec::as_slice);
+
+    for &start in tree.keys() {
+        if index.contains_key(&start) {
+            continue;
+        }
+
+        let mut frames = vec![(start, 0)];
+        index.insert(start, index.len());
+        lowlink.insert(start, lowlink.len());
+        stack.push(start);
+        on_stack.insert(start);
+
+        while let Some((node, next)) = frames.last_mut() {
+            let node = *node;
+            if let Some(&child) = children(node).get(*next) {
+                *next += 1;
+                if !tree.contains_key(&child) {
+                    // Leaves can't be part of a cycle.
+                } else if !index.contains_key(&child) {
+                    index.insert(child, index.len());
+                    lowlink.insert(child, lowlink.len());
+                    stack.push(child);
+                    on_stack.insert(child);
+                    frames.push((child, 0));
+                } else if on_stack.contains(&child) {
+                    lowlink.insert(node, lowlink[&node].min(index[&child]));
+                }
+                continue;
+            }
+
+            frames.pop();
+            if let Some(&(parent, _)) = frames.last() {
+                lowlink.insert(parent, lowlink[&parent].min(lowlink[&node]));
             }
-            Entry::Vacant(entry) => {
-                entry.insert(path.clone());
+            if lowlink[&node] == index[&node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack.remove(&member);
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                if component.len() > 1 || children(node).contains(&node) {
+                    cycles.push(component);
+                }
             }
         }
-        return;
     }
 
-    let Some(children) = tree.get(&conductor) else {
-        return;
-    };
+    cycles
+}
+
+/// Walk every path from the root to a fruit, grouping them by length.
+///
+/// Uses an explicit stack so that deep trees don't overflow the call stack, and never steps into `pruned` nodes.
+fn walk<Id: Hash + Eq + Copy, IsFruit: Fn(Id) -> bool>(
+    tree: &Tree<Id>,
+    root: Id,
+    pruned: &HashSet<Id>,
+    is_fruit: &IsFruit,
+) -> (BTreeMap<usize, Vec<Vec<Id>>>, HashSet<Id>) {
+    let mut paths = BTreeMap::<_, Vec<_>>::new();
+    let mut visited = HashSet::default();
+    if pruned.contains(&root) {
+        return (paths, visited);
+    }
+
+    let mut path = vec![root];
+    let mut next_child = vec![0];
+    visited.insert(root);
+
+    while let Some(next) = next_child.last_mut() {
+        let &conductor = path.last().unwrap();
+        let Some(&node) = tree.get(&conductor).and_then(|children| children.get(*next)) else {
+            next_child.pop();
+            path.pop();
+            continue;
+        };
+        *next += 1;
+        if pruned.contains(&node) {
+            continue;
+        }
 
-    children.iter().for_each(|&node| {
         path.push(node);
-        walk::<Id, IsFruit>(tree, path, paths, is_fruit);
-        path.pop();
-    })
-}
-
-fn solve<
-    const CHECK_FOR_BUGS: bool,
-    Id: Hash + Eq + Copy,
-    ConvertId: Fn(&'static str) -> Id,
-    IsFruit: Fn(Id) -> bool,
->(
-    input: &'static str,
-    convert_id: ConvertId,
+        if is_fruit(node) {
+            paths.entry(path.len()).or_default().push(path.clone());
+            path.pop();
+        } else {
+            visited.insert(node);
+            next_child.push(0);
+        }
+    }
+
+    (paths, visited)
+}
+
+fn solve<'a, Id: Hash + Ord + Copy, ConvertId: FnMut(&'a str) -> Id, IsFruit: Fn(Id) -> bool>(
+    input: &'a str,
+    mut convert_id: ConvertId,
     is_fruit: IsFruit,
-) -> Vec<Id> {
+) -> (BTreeMap<usize, Vec<Vec<Id>>>, Diagnostics<Id>) {
     let mut nodes = HashMap::default();
+    let mut redefined = Vec::new();
+    let mut parents = HashMap::default();
     input.lines().for_each(|line| {
         let (node, children) = line.split_once(':').unwrap();
-        if CHECK_FOR_BUGS && matches!(node, "ANT" | "BUG") {
-            return;
+        let children = children.split(',').map(&mut convert_id).collect::<Vec<_>>();
+        children
+            .iter()
+            .filter(|&&child| !is_fruit(child))
+            .for_each(|&child| *parents.entry(child).or_insert(0) += 1);
+        if nodes.insert(convert_id(node), children).is_some() {
+            redefined.push(convert_id(node));
         }
-        let children = children
-            .split(',')
-            .filter(|&s| !(CHECK_FOR_BUGS && matches!(s, "ANT" | "BUG")))
-            .map(&convert_id)
-            .collect::<Vec<_>>();
-        nodes.insert(convert_id(node), children);
     });
 
-    let mut paths = Default::default();
-    walk::<Id, IsFruit>(&nodes, &mut vec![convert_id("RR")], &mut paths, &is_fruit);
-    debug_assert_eq!(paths.len(), 1);
-    paths.into_values().next().unwrap()
-}
-
-pub fn solve_part1(input: &'static str) -> String {
-    type Id = [u8; 2];
-    const FRUIT: Id = [b'\0', b'@'];
-    solve::<false, _, _, _>(
-        input,
-        |s| match s {
-            "@" => FRUIT,
-            _ => Id::try_from(s.as_bytes()).unwrap(),
-        },
-        |s| s == FRUIT,
-    )
-    .into_iter()
-    .fold(String::with_capacity(13), |mut acc, s| {
-        acc.push_str(match s {
-            FRUIT => "@",
-            _ => std::str::from_utf8(&s[..]).unwrap(),
-        });
-        acc
-    })
-}
-
-fn solve_part23<const CHECK_CYCLES: bool>(input: &'static str) -> String {
-    type Id = [u8; 4];
-    const FRUIT: Id = [b'\0', b'\0', b'\0', b'@'];
-    const ROOT: Id = [b'\0', b'\0', b'R', b'R'];
-    solve::<CHECK_CYCLES, _, _, _>(
-        input,
-        |s| match s {
-            "@" => FRUIT,
-            "RR" => ROOT,
-            _ => Id::try_from(s.as_bytes()).unwrap(),
-        },
-        |s| s == FRUIT,
-    )
-    .into_iter()
-    .fold(String::with_capacity(13), |mut acc, s| {
-        acc.push(match s {
-            FRUIT => '@',
-            ROOT => 'R',
-            _ => s[0] as char,
-        });
-        acc
-    })
-}
-
-pub fn solve_part2(input: &'static str) -> String {
-    solve_part23::<false>(input)
-}
-
-pub fn solve_part3(input: &'static str) -> String {
-    solve_part23::<true>(input)
+    // Anything on a cycle would lead to infinitely many paths, so leave it out entirely.
+    let mut cycles = find_cycles(&nodes);
+    let pruned = cycles.iter().flatten().copied().collect::<HashSet<_>>();
+
+    let (paths, visited) = walk::<Id, IsFruit>(&nodes, convert_id("RR"), &pruned, &is_fruit);
+
+    let mut shared = parents
+        .into_iter()
+        .filter_map(|(node, count)| (count > 1).then_some(node))
+        .collect::<Vec<_>>();
+    let mut unreachable = nodes
+        .keys()
+        .copied()
+        .filter(|node| !visited.contains(node) && !pruned.contains(node))
+        .collect::<Vec<_>>();
+    cycles.iter_mut().for_each(|cycle| cycle.sort_unstable());
+    cycles.sort_unstable();
+    redefined.sort_unstable();
+    redefined.dedup();
+    shared.sort_unstable();
+    unreachable.sort_unstable();
+
+    let diagnostics = Diagnostics {
+        cycles,
+        redefined,
+        shared,
+        unreachable,
+    };
+    (paths, diagnostics)
+}
+
+/// The path to the most powerful fruit, i.e. the only one whose length no other path shares, if there's exactly one.
+fn most_powerful<Id>(paths: &BTreeMap<usize, Vec<Vec<Id>>>) -> Option<&[Id]> {
+    let mut unique = paths.values().filter(|group| group.len() == 1);
+    let path = unique.next()?;
+    unique.next().is_none().then_some(&path[0][..])
+}
+
+/// Node names of any length, interned into dense integer ids.
+#[derive(Debug, Default)]
+struct Interner<'a> {
+    ids: HashMap<&'a str, u32>,
+    names: Vec<&'a str>,
+}
+
+impl<'a> Interner<'a> {
+    fn intern(&mut self, name: &'a str) -> u32 {
+        *self.ids.entry(name).or_insert_with(|| {
+            self.names.push(name);
+            self.names.len() as u32 - 1
+        })
+    }
+
+    fn name(&self, id: u32) -> &'a str {
+        self.names[id as usize]
+    }
+}
+
+/// Pack a name of at most `N` bytes into a compact id, right-aligned and padded with NULs.
+fn pack<const N: usize>(name: &str) -> Option<[u8; N]> {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() || bytes.len() > N || bytes.contains(&0) {
+        return None;
+    }
+    let mut id = [0; N];
+    id[N - bytes.len()..].copy_from_slice(bytes);
+    Some(id)
+}
+
+fn unpack<const N: usize>(id: &[u8; N]) -> &str {
+    let start = id.iter().position(|&b| b != 0).unwrap();
+    std::str::from_utf8(&id[start..]).unwrap()
+}
+
+/// Find the most powerful fruit and spell out the path leading to it, either with full node names or just their
+/// initials.
+///
+/// If every name fits in `N` bytes this packs them into compact ids, otherwise it falls back to interning them.
+fn solve_spelled<const N: usize>(input: &str, initials_only: bool) -> String {
+    let spell = |name: &str| {
+        if initials_only {
+            name.chars().next().unwrap().to_string()
+        } else {
+            name.to_owned()
+        }
+    };
+
+    let path = if input
+        .lines()
+        .flat_map(|line| line.split([':', ',']))
+        .all(|name| pack::<N>(name).is_some())
+    {
+        let fruit = pack::<N>("@").unwrap();
+        let (paths, _) = solve(input, |name| pack::<N>(name).unwrap(), |id| id == fruit);
+        most_powerful(&paths).map(|path| path.iter().map(unpack).map(spell).collect())
+    } else {
+        let mut interner = Interner::default();
+        let fruit = interner.intern("@");
+        let (paths, _) = solve(input, |name| interner.intern(name), |id| id == fruit);
+        most_powerful(&paths).map(|path| path.iter().map(|&id| spell(interner.name(id))).collect())
+    };
+
+    path.expect("there should be exactly one path with a unique length")
+}
+
+/// Every fruit path in a tree, along with what's wrong with it.
+#[derive(Debug, Clone)]
+pub struct Inspection<'a> {
+    /// Every path from the root to a fruit, grouped by length.
+    pub paths: BTreeMap<usize, Vec<Vec<&'a str>>>,
+    pub diagnostics: Diagnostics<&'a str>,
+    /// The edges of the tree as walked, in input order.
+    edges: Vec<(&'a str, &'a str)>,
+}
+
+impl Inspection<'_> {
+    /// The path to the most powerful fruit, if exactly one path has a length no other path shares.
+    pub fn most_powerful(&self) -> Option<&[&str]> {
+        most_powerful(&self.paths)
+    }
+
+    /// Render the tree in Graphviz's DOT language, highlighting the path to the most powerful fruit and greying out
+    /// the nodes that were pruned or never reached.
+    pub fn to_dot(&self) -> String {
+        let mut highlighted: HashSet<(&str, &str)> = self
+            .most_powerful()
+            .map(|path| path.windows(2).map(|pair| (pair[0], pair[1])).collect())
+            .unwrap_or_default();
+        let greyed = self
+            .diagnostics
+            .cycles
+            .iter()
+            .flatten()
+            .chain(&self.diagnostics.unreachable);
+
+        let mut dot = String::from("digraph tree {\n");
+        writeln!(dot, "    {:?} [shape=doublecircle];", "RR").unwrap();
+        for node in greyed {
+            writeln!(dot, "    {node:?} [color=gray, fontcolor=gray, style=dashed];").unwrap();
+        }
+        for (i, &(parent, child)) in self.edges.iter().enumerate() {
+            // Every fruit gets its own node, otherwise they'd all get squashed together.
+            let target = if child == "@" {
+                writeln!(dot, "    \"@{i}\" [label=\"@\", shape=point];").unwrap();
+                format!("\"@{i}\"")
+            } else {
+                format!("{child:?}")
+            };
+            // Only highlight the first of several identical edges.
+            let style = if highlighted.remove(&(parent, child)) {
+                " [color=red, penwidth=3]"
+            } else {
+                ""
+            };
+            writeln!(dot, "    {parent:?} -> {target}{style};").unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Collect every fruit path and report cycles, redefinitions, shared nodes and unreachable nodes in the input.
+pub fn inspect(input: &str) -> Inspection<'_> {
+    let mut interner = Interner::default();
+    let fruit = interner.intern("@");
+    let (paths, diagnostics) = solve(input, |name| interner.intern(name), |id| id == fruit);
+
+    let names = |ids: Vec<u32>| ids.into_iter().map(|id| interner.name(id)).collect::<Vec<_>>();
+    // The ids are in order of first appearance, so the diagnostics have to be sorted again by name.
+    let sorted_names = |ids: Vec<u32>| {
+        let mut names = names(ids);
+        names.sort_unstable();
+        names
+    };
+    let paths = paths
+        .into_iter()
+        .map(|(len, group)| (len, group.into_iter().map(names).collect()))
+        .collect();
+    let mut cycles = diagnostics.cycles.into_iter().map(sorted_names).collect::<Vec<_>>();
+    cycles.sort_unstable();
+    let diagnostics = Diagnostics {
+        cycles,
+        redefined: sorted_names(diagnostics.redefined),
+        shared: sorted_names(diagnostics.shared),
+        unreachable: sorted_names(diagnostics.unreachable),
+    };
+
+    // Only the last listing of a node's children counts.
+    let definitions = input
+        .lines()
+        .map(|line| line.split_once(':').unwrap())
+        .collect::<Vec<_>>();
+    let last_definition = definitions
+        .iter()
+        .enumerate()
+        .map(|(i, &(node, _))| (node, i))
+        .collect::<HashMap<_, _>>();
+    let edges = definitions
+        .iter()
+        .enumerate()
+        .filter(|&(i, &(node, _))| last_definition[node] == i)
+        .flat_map(|(_, &(node, children))| children.split(',').map(move |child| (node, child)))
+        .collect();
+
+    Inspection {
+        paths,
+        diagnostics,
+        edges,
+    }
+}
+
+/// Report cycles, redefinitions, shared nodes and unreachable nodes in the input.
+pub fn diagnose(input: &str) -> Diagnostics<&str> {
+    inspect(input).diagnostics
+}
+
+pub fn solve_part1(input: &str) -> String {
+    solve_spelled::<2>(input, false)
+}
+
+pub fn solve_part2(input: &str) -> String {
+    solve_spelled::<4>(input, true)
+}
+
+pub fn solve_part3(input: &str) -> String {
+    // The ANT and BUG branches loop back on themselves, so they get pruned along with every other cycle.
+    solve_spelled::<4>(input, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics() {
+        let input = "RR:ZZ,YY\nZZ:ZZ,@\nYY:XX,WW\nXX:WW\nWW:XX\nVV:@\nYY:CC,BB,CC\nCC:@\nBB:@";
+        assert_eq!(
+            diagnose(input),
+            Diagnostics {
+                cycles: vec![vec!["WW", "XX"], vec!["ZZ"]],
+                redefined: vec!["YY"],
+                shared: vec!["CC", "WW", "XX", "ZZ"],
+                unreachable: vec!["VV"],
+            }
+        );
+    }
+
+    #[test]
+    fn test_clean_tree() {
+        let diagnostics = diagnose(include_str!("part1_sample.txt"));
+        assert!(diagnostics.cycles.is_empty());
+        assert!(diagnostics.redefined.is_empty());
+        assert!(diagnostics.shared.is_empty());
+        assert!(diagnostics.unreachable.is_empty());
+    }
+
+    #[test]
+    fn test_most_powerful() {
+        assert_eq!(
+            inspect(include_str!("part1_sample.txt")).most_powerful(),
+            Some(&["RR", "B", "@"][..])
+        );
+        // Every path has a length of its own, so none of them stands out.
+        assert_eq!(inspect("RR:A,@\nA:@,B\nB:@").most_powerful(), None);
+        // The ANT and BUG branches are pruned as cycles without having to single them out.
+        assert_eq!(solve_part3(include_str!("part3.txt")), "RFXHWRHJNHZX@");
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let inspection = inspect("RR:A,B\nA:@\nB:@,C\nC:@\nXX:@");
+        assert_eq!(
+            inspection.to_dot(),
+            r#"digraph tree {
+    "RR" [shape=doublecircle];
+    "XX" [color=gray, fontcolor=gray, style=dashed];
+    "RR" -> "A";
+    "RR" -> "B" [color=red, penwidth=3];
+    "@2" [label="@", shape=point];
+    "A" -> "@2";
+    "@3" [label="@", shape=point];
+    "B" -> "@3";
+    "B" -> "C" [color=red, penwidth=3];
+    "@5" [label="@", shape=point];
+    "C" -> "@5" [color=red, penwidth=3];
+    "@6" [label="@", shape=point];
+    "XX" -> "@6";
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_long_names() {
+        // LONGNAME doesn't fit in a packed id, so this goes through the interner instead.
+        let input = "RR:LONGNAME,BB\nLONGNAME:@,XYZW\nBB:@\nXYZW:@";
+        assert_eq!(solve_part1(input), "RRLONGNAMEXYZW@");
+        assert_eq!(solve_part2(input), "RLX@");
+        assert_eq!(solve_part1(include_str!("part1_sample.txt")), "RRB@");
+    }
 }
@@ -1,16 +1,20 @@
+use std::collections::HashSet;
+
 use rayon::prelude::*;
 
-const PART2_LOOPS: usize = 10;
+const PART1_SEGMENTS: u64 = 10;
+
+const PART2_LOOPS: u64 = 10;
 
-const PART3_PLAN_LEN: usize = 11;
+const PART3_LOOPS: u64 = 2024;
 
-// We know the map to be 340 cells long and the plan to be 11 cells long, therefore the pairs repeat every lcm(340, 11)
-// = 3740 cells. We can therefore run through this macroloop once (3740 iterations) and assume the rest of the
-// macroloops would add the same amout to the power (and therefore to the total, in a quadratic way).
-const PART3_LOOPS: usize = PART3_PLAN_LEN;
+// The number of each kind of action in a part 3 plan.
+const PART3_ADDS: usize = 5;
+const PART3_SUBS: usize = 3;
+const PART3_STAYS: usize = 3;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Action {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
     Add,
     Sub,
     Stay,
@@ -65,23 +69,202 @@ pub fn solve_part1(input: &str) -> String {
     plans.into_iter().map(|(name, _)| name).collect()
 }
 
+fn parse_plans(input: &str) -> impl Iterator<Item = (&str, Vec<Action>)> {
+    input.lines().map(|line| {
+        let (name, plan) = line.split_once(':').unwrap();
+        (name, plan.bytes().step_by(2).map(Action::from).collect())
+    })
+}
+
 pub fn solve_part2(input: &str) -> String {
-    let part2_map = linearize_map(include_str!("part2_map.txt"));
-    let mut plans = input
-        .lines()
-        .map(|line| {
-            let (name, plan) = line.split_once(':').unwrap();
-            let plan = plan.bytes().step_by(2).map(Action::from).collect::<Vec<_>>();
-            (name, execute_plan(&part2_map, &plan, PART2_LOOPS))
-        })
+    let part2_map = linearize_track(include_str!("part2_map.txt")).unwrap();
+    let mut plans = parse_plans(input)
+        .map(|(name, plan)| (name, execute_plan(&part2_map, &plan, PART2_LOOPS).unwrap()))
         .collect::<Vec<_>>();
     plans.sort_by_key(|(_, power)| *power);
     plans.reverse();
     plans.into_iter().map(|(name, _)| name).collect()
 }
 
+/// A segment-by-segment account of a race, to explain where a chariot gained or lost ground.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// The power right after each segment.
+    pub powers: Vec<i128>,
+    /// The essence gathered so far at the end of each loop.
+    pub essence_per_loop: Vec<i128>,
+    /// The essence gathered by the end of the race.
+    pub total: i128,
+}
+
+/// Step through a race one segment at a time, with the same conventions as `execute_plan`.
+pub fn replay(map: &[Action], plan: &[Action], loops: u64) -> Result<Replay, RaceError> {
+    check_race(map, plan)?;
+    let steps = loops as usize * map.len() + 1;
+    let mut powers = Vec::with_capacity(steps);
+    let mut essence_per_loop = Vec::with_capacity(loops as usize);
+    let mut power = 10;
+    let mut total = 0;
+
+    for i in 0..steps {
+        let map_idx = (i + 1) % map.len();
+        power += segment_effect(map[map_idx], plan[i % plan.len()]);
+        total += power;
+        powers.push(power);
+        // Going through the S marks the end of a loop.
+        if map_idx == 0 {
+            essence_per_loop.push(total);
+        }
+    }
+
+    Ok(Replay {
+        powers,
+        essence_per_loop,
+        total,
+    })
+}
+
+/// How a segment changes the power, given the track's action there and the plan's.
+fn segment_effect(map_action: Action, plan_action: Action) -> i128 {
+    match (map_action, plan_action) {
+        (Action::Add, _) | (Action::Stay, Action::Add) => 1,
+        (Action::Sub, _) | (Action::Stay, Action::Sub) => -1,
+        (Action::Stay, Action::Stay) => 0,
+    }
+}
+
+/// Replay every part 1 plan. Part 1 has no track to speak of, which is the same as looping around a single neutral
+/// cell, so there's an entry in `essence_per_loop` for every segment.
+pub fn replay_part1(input: &str) -> Vec<(&str, Replay)> {
+    parse_plans(input)
+        .map(|(name, plan)| (name, replay(&[Action::Stay], &plan, PART1_SEGMENTS - 1).unwrap()))
+        .collect()
+}
+
+/// Replay every part 2 plan on its track.
+pub fn replay_part2(input: &str) -> Vec<(&str, Replay)> {
+    let part2_map = linearize_track(include_str!("part2_map.txt")).unwrap();
+    parse_plans(input)
+        .map(|(name, plan)| (name, replay(&part2_map, &plan, PART2_LOOPS).unwrap()))
+        .collect()
+}
+
+/// Draw the track with the effect each segment has on the power during the given loop (counting from 0) of a race
+/// following `plan`.
+pub fn render_loop(map: &str, plan: &[Action], lap: u64) -> Result<String, RaceError> {
+    let track = trace_track(map)?;
+    if plan.is_empty() {
+        return Err(RaceError::EmptyPlan);
+    }
+    let mut grid = map
+        .lines()
+        .map(|line| line.bytes().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let start = lap as usize * track.len();
+    for i in start..start + track.len() {
+        let ((x, y), map_action) = track[(i + 1) % track.len()];
+        if map_action == Action::Stay && grid[y][x] == b'S' {
+            continue;
+        }
+        grid[y][x] = match segment_effect(map_action, plan[i % plan.len()]) {
+            1 => b'+',
+            -1 => b'-',
+            _ => b'=',
+        };
+    }
+
+    Ok(grid
+        .into_iter()
+        .map(|row| String::from_utf8(row).unwrap() + "\n")
+        .collect())
+}
+
+/// Every distinct plan using a given number of each action, generated lazily in lexicographic order.
+#[derive(Debug, Clone)]
+pub struct Plans {
+    next: Option<Vec<Action>>,
+}
+
+impl Plans {
+    pub fn new(adds: usize, subs: usize, stays: usize) -> Self {
+        let mut first = vec![Action::Add; adds];
+        first.extend(std::iter::repeat_n(Action::Sub, subs));
+        first.extend(std::iter::repeat_n(Action::Stay, stays));
+        Self { next: Some(first) }
+    }
+}
+
+impl Iterator for Plans {
+    type Item = Vec<Action>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let plan = self.next.take()?;
+
+        // Standard next permutation: find the last ascent, swap its left side with the last element bigger than it,
+        // and put everything after it back in ascending order. Repeated actions are naturally only ordered once.
+        let mut next = plan.clone();
+        if let Some(i) = (1..next.len()).rev().find(|&i| next[i - 1] < next[i]) {
+            let j = (i..next.len()).rev().find(|&j| next[j] > next[i - 1]).unwrap();
+            next.swap(i - 1, j);
+            next[i..].reverse();
+            self.next = Some(next);
+        }
+
+        Some(plan)
+    }
+}
+
+/// The outcome of trying every possible plan against a rival's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Optimization {
+    /// The plan gathering the most essence, preferring the first one in lexicographic order in case of ties.
+    pub best_plan: Vec<Action>,
+    pub best_score: i128,
+    /// How many plans gather strictly more essence than the rival's.
+    pub beating_rival: usize,
+}
+
+/// Race every plan with the given action counts against the rival's, without ever holding more than a few in memory.
+pub fn optimize(
+    map: &[Action],
+    rival: &[Action],
+    (adds, subs, stays): (usize, usize, usize),
+    loops: u64,
+) -> Result<Optimization, RaceError> {
+    let target = execute_plan(map, rival, loops)?;
+    if adds + subs + stays == 0 {
+        return Err(RaceError::EmptyPlan);
+    }
+
+    let best = |a: (i128, Vec<Action>), b: (i128, Vec<Action>)| {
+        if (b.0, &a.1) > (a.0, &b.1) {
+            b
+        } else {
+            a
+        }
+    };
+
+    let ((best_score, best_plan), beating_rival) = Plans::new(adds, subs, stays)
+        .par_bridge()
+        .map(|plan| -> Result<_, RaceError> {
+            let score = execute_plan(map, &plan, loops)?;
+            Ok(((score, plan), usize::from(score > target)))
+        })
+        .try_reduce(
+            || ((i128::MIN, Vec::new()), 0),
+            |(a, a_count), (b, b_count)| Ok((best(a, b), a_count + b_count)),
+        )?;
+
+    Ok(Optimization {
+        best_plan,
+        best_score,
+        beating_rival,
+    })
+}
+
 pub fn solve_part3(input: &str) -> usize {
-    let part3_map = linearize_map(include_str!("part3_map.txt"));
+    let part3_map = linearize_track(include_str!("part3_map.txt")).unwrap();
 
     let enemy_plan = input
         .split_once(':')
@@ -91,78 +274,77 @@ pub fn solve_part3(input: &str) -> usize {
         .step_by(2)
         .map(Action::from)
         .collect::<Vec<_>>();
-    let target = execute_plan(&part3_map, &enemy_plan, PART3_LOOPS);
-
-    let mut possible_plans = Vec::new();
-    all_possible_plans(5, 3, 3, &mut possible_plans, &mut Vec::new());
-
-    possible_plans
-        .into_par_iter()
-        .filter(|plan| execute_plan(&part3_map, plan, PART3_LOOPS) > target)
-        .count()
-}
-
-fn all_possible_plans(
-    pluses_remaining: usize,
-    minuses_remaining: usize,
-    equals_remaining: usize,
-    output: &mut Vec<Vec<Action>>,
-    current: &mut Vec<Action>,
-) {
-    if pluses_remaining != 0 {
-        current.push(Action::Add);
-        all_possible_plans(
-            pluses_remaining - 1,
-            minuses_remaining,
-            equals_remaining,
-            output,
-            current,
-        );
-        current.pop();
-    }
-    if minuses_remaining != 0 {
-        current.push(Action::Sub);
-        all_possible_plans(
-            pluses_remaining,
-            minuses_remaining - 1,
-            equals_remaining,
-            output,
-            current,
-        );
-        current.pop();
-    }
-    if equals_remaining != 0 {
-        current.push(Action::Stay);
-        all_possible_plans(
-            pluses_remaining,
-            minuses_remaining,
-            equals_remaining - 1,
-            output,
-            current,
-        );
-        current.pop();
+
+    optimize(
+        &part3_map,
+        &enemy_plan,
+        (PART3_ADDS, PART3_SUBS, PART3_STAYS),
+        PART3_LOOPS,
+    )
+    .unwrap()
+    .beating_rival
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
+}
+
+/// Why a race couldn't be run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RaceError {
+    /// The track has no cells to go around.
+    EmptyTrack,
+    /// The plan has no actions to repeat.
+    EmptyPlan,
+    /// The essence gathered doesn't fit in an `i128`.
+    Overflow,
+    /// The track map couldn't be followed.
+    Track(TrackError),
+}
 
-    if pluses_remaining == 0 && minuses_remaining == 0 && equals_remaining == 0 {
-        debug_assert_eq!(current.len(), PART3_PLAN_LEN);
-        output.push(current.clone());
+impl From<TrackError> for RaceError {
+    fn from(err: TrackError) -> Self {
+        RaceError::Track(err)
     }
 }
 
-fn execute_plan(map: &[Action], plan: &[Action], n: usize) -> u64 {
-    let mut power = 10u64;
-    let mut total = 0u64;
+impl std::fmt::Display for RaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RaceError::EmptyTrack => write!(f, "the track is empty"),
+            RaceError::EmptyPlan => write!(f, "the plan is empty"),
+            RaceError::Overflow => write!(f, "the race is too long to count its essence"),
+            RaceError::Track(err) => write!(f, "{err}"),
+        }
+    }
+}
 
-    let map_len = map.len();
-    let plan_len = plan.len();
+impl std::error::Error for RaceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RaceError::Track(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
-    // Calculate the indices such that:
-    // 1) The race starts at the first cell after the S;
-    // 2) The race ends at the S, after however many loops we've been asked to do;
-    // 3) The plan starts at the first cell after the S.
-    let mut map_idx = 1;
-    let mut plan_idx = 0;
-    let total_iterations = n * map_len + 1;
+/// Neither the track nor the plan can be empty, as both are repeated for the whole race.
+fn check_race(map: &[Action], plan: &[Action]) -> Result<(), RaceError> {
+    if map.is_empty() {
+        Err(RaceError::EmptyTrack)
+    } else if plan.is_empty() {
+        Err(RaceError::EmptyPlan)
+    } else {
+        Ok(())
+    }
+}
+
+fn execute_plan(map: &[Action], plan: &[Action], loops: u64) -> Result<i128, RaceError> {
+    check_race(map, plan)?;
 
     // Precalculated lookup table for the power change.
     const DELTA_TABLE: [[i8; 3]; 3] = [
@@ -172,70 +354,337 @@ fn execute_plan(map: &[Action], plan: &[Action], n: usize) -> u64 {
         [1, -1, 0],   // map_action: Stay
     ];
 
-    for _ in 0..total_iterations {
-        // Find the action pair for this iteration.
-        let map_action = map[map_idx];
-        let plan_action = plan[plan_idx];
+    let map_len = map.len();
+    let plan_len = plan.len();
+
+    // The race starts at the first cell after the S, with the plan starting there too, and goes on for however many
+    // loops we've been asked to do plus a step.
+    let steps = u128::from(loops) * map_len as u128 + 1;
+
+    // The (map, plan) action pairs repeat every lcm(map_len, plan_len) steps, so the power goes up by the same amount
+    // during each of these periods. Work out what happens during a single one, then add up all of them in closed form.
+    let period = (map_len / gcd(map_len, plan_len))
+        .checked_mul(plan_len)
+        .ok_or(RaceError::Overflow)?;
+    let full_periods = i128::try_from(steps / period as u128).map_err(|_| RaceError::Overflow)?;
+    let rest = (steps % period as u128) as usize;
+
+    // The power relative to the start of a period after each of its steps, added up over the whole period and over
+    // the `rest` steps after the last full one. A race shorter than a period has no full ones, so it stops there.
+    let mut gained = 0i128;
+    let mut offsets_total = 0i128;
+    let mut rest_offsets_total = 0i128;
+    for i in 0..period.min(steps.try_into().unwrap_or(usize::MAX)) {
+        let map_action = map[(i + 1) % map_len];
+        let plan_action = plan[i % plan_len];
+        gained += i128::from(DELTA_TABLE[map_action.to_index()][plan_action.to_index()]);
+        offsets_total += gained;
+        if i < rest {
+            rest_offsets_total += gained;
+        }
+    }
+    let period = period as i128;
+
+    // Period `k` starts at a power of `10 + k * gained`, and adds up to `period` times that plus the offsets. With
+    // enough loops this no longer fits in an `i128`, well before `loops` reaches `u64::MAX`.
+    let checked = || -> Option<i128> {
+        // Halve whichever of the two consecutive numbers is even, so the product itself doesn't have to fit.
+        let pairs = if full_periods % 2 == 0 {
+            (full_periods / 2).checked_mul(full_periods - 1)?
+        } else {
+            full_periods.checked_mul((full_periods - 1) / 2)?
+        };
+        let full_total = full_periods
+            .checked_mul(period)?
+            .checked_mul(10)?
+            .checked_add(period.checked_mul(gained)?.checked_mul(pairs)?)?
+            .checked_add(full_periods.checked_mul(offsets_total)?)?;
+        let power = full_periods.checked_mul(gained)?.checked_add(10)?;
+        let rest_total = (rest as i128).checked_mul(power)?.checked_add(rest_offsets_total)?;
+        full_total.checked_add(rest_total)
+    };
+
+    checked().ok_or(RaceError::Overflow)
+}
 
-        // Use precomputed delta table to get the power change.
-        let delta = DELTA_TABLE[map_action.to_index()][plan_action.to_index()] as i64;
-        power = power.wrapping_add(delta as u64);
-        total = total.wrapping_add(power);
+/// A cell of a track, as `(x, y)`.
+pub type Position = (usize, usize);
+
+/// Why a track map couldn't be turned into a sequence of actions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackError {
+    /// There's no `S` anywhere on the map.
+    NoStart,
+    /// There's more than one `S`.
+    MultipleStarts(Position, Position),
+    /// A cell holds something other than a space, `+`, `-`, `=` or `S`.
+    InvalidCell(Position, char),
+    /// The track splits in two and it's unclear which way to go.
+    Fork(Position),
+    /// The track stops without going back to the start.
+    DeadEnd(Position),
+    /// The track loops forever without ever going back to the start.
+    NoReturn(Position),
+    /// Some track cells are never reached from the start.
+    Disconnected(Vec<Position>),
+}
 
-        // Increment indices with wrap-around.
-        map_idx = if map_idx + 1 == map_len { 0 } else { map_idx + 1 };
-        plan_idx = if plan_idx + 1 == plan_len { 0 } else { plan_idx + 1 };
+impl std::fmt::Display for TrackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrackError::NoStart => write!(f, "the track has no start"),
+            TrackError::MultipleStarts(a, b) => write!(f, "the track has starts at both {a:?} and {b:?}"),
+            TrackError::InvalidCell(pos, c) => write!(f, "invalid cell {c:?} at {pos:?}"),
+            TrackError::Fork(pos) => write!(f, "the track forks at {pos:?}"),
+            TrackError::DeadEnd(pos) => write!(f, "the track dead-ends at {pos:?}"),
+            TrackError::NoReturn(pos) => {
+                write!(f, "the track loops forever from {pos:?} without returning to the start")
+            }
+            TrackError::Disconnected(cells) => {
+                write!(f, "{} track cells are disconnected, e.g. {:?}", cells.len(), cells[0])
+            }
+        }
     }
+}
 
-    total
+impl std::error::Error for TrackError {}
+
+/// Follow the track from its `S`, wherever that is, until it comes back around, returning every action along the way.
+///
+/// The race sets off towards the first of right, down, left and up that has track on it. The track is followed
+/// straight ahead whenever possible, so it may cross itself, and otherwise turns whichever single way it can.
+pub fn linearize_track(map: &str) -> Result<Vec<Action>, TrackError> {
+    Ok(trace_track(map)?.into_iter().map(|(_, action)| action).collect())
 }
 
-fn linearize_map(map: &str) -> Vec<Action> {
+/// Like [`linearize_track`], but keeping the position of every cell along with its action.
+fn trace_track(map: &str) -> Result<Vec<(Position, Action)>, TrackError> {
     let map = map
         .lines()
         .map(|line| line.bytes().collect::<Vec<_>>())
         .collect::<Vec<_>>();
-    let mut result = Vec::with_capacity(map.len() * map[0].len());
-
-    let mut x = 0;
-    let mut y = 0;
-    let mut dx = 1;
-    let mut dy = 0;
-    debug_assert_eq!(map[y][x], b'S');
 
-    let mut saw_start = false;
+    let mut start = None;
+    for (y, row) in map.iter().enumerate() {
+        for (x, &b) in row.iter().enumerate() {
+            match b {
+                b'S' => match start {
+                    Some(other) => return Err(TrackError::MultipleStarts(other, (x, y))),
+                    None => start = Some((x, y)),
+                },
+                b'+' | b'-' | b'=' | b' ' => (),
+                _ => return Err(TrackError::InvalidCell((x, y), b as char)),
+            }
+        }
+    }
+    let start = start.ok_or(TrackError::NoStart)?;
 
-    let next_cell = |x: usize, y: usize, dx: isize, dy: isize| {
+    let next_cell = |(x, y): Position, (dx, dy): (isize, isize)| {
         let y = y.checked_add_signed(dy)?;
         let x = x.checked_add_signed(dx)?;
         let row = map.get(y)?;
-        row.get(x).copied()
+        row.get(x).filter(|&&c| c != b' ').map(|_| (x, y))
     };
 
+    let (x, y) = start;
+    let mut result = vec![(start, Action::from(map[y][x]))];
+    let mut visited = map.iter().map(|row| vec![false; row.len()]).collect::<Vec<_>>();
+    visited[y][x] = true;
+    let mut seen_states = HashSet::new();
+
+    let mut dir = [(1, 0), (0, 1), (-1, 0), (0, -1)]
+        .into_iter()
+        .find(|&dir| next_cell(start, dir).is_some())
+        .ok_or(TrackError::DeadEnd(start))?;
+    let mut pos = start;
+
     loop {
-        let b = map[y][x];
-        if b == b'S' {
-            if saw_start {
-                break;
-            }
-            saw_start = true;
+        let (dx, dy) = dir;
+        // Turning left or right, respectively.
+        let sides = [(dy, -dx), (-dy, dx)].map(|side| next_cell(pos, side).map(|_| side));
+        dir = match (next_cell(pos, dir), sides) {
+            // Both turns being available means this is a crossing, so keep going straight.
+            (Some(_), [None, None] | [Some(_), Some(_)]) => dir,
+            (Some(_), _) if pos != start => return Err(TrackError::Fork(pos)),
+            (Some(_), _) => dir,
+            (None, [Some(side), None] | [None, Some(side)]) => side,
+            (None, [Some(_), Some(_)]) => return Err(TrackError::Fork(pos)),
+            (None, [None, None]) => return Err(TrackError::DeadEnd(pos)),
+        };
+        if !seen_states.insert((pos, dir)) {
+            return Err(TrackError::NoReturn(pos));
         }
-        result.push(Action::from(b));
-
-        if matches!(next_cell(x, y, dx, dy), Some(b' ') | None) {
-            let (new_dx, new_dy, _) = [(1, 0), (0, 1), (-1, 0), (0, -1)]
-                .into_iter()
-                .filter(|&new_dir| new_dir != (-dx, -dy))
-                .filter_map(|(new_dx, new_dy)| next_cell(x, y, new_dx, new_dy).map(|c| (new_dx, new_dy, c)))
-                .find(|&(_, _, c)| c != b' ')
-                .unwrap();
-            dx = new_dx;
-            dy = new_dy;
+
+        pos = next_cell(pos, dir).unwrap();
+        if pos == start {
+            break;
+        }
+        visited[pos.1][pos.0] = true;
+        result.push((pos, Action::from(map[pos.1][pos.0])));
+    }
+
+    let disconnected = map
+        .iter()
+        .enumerate()
+        .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, &b)| ((x, y), b)))
+        .filter(|&((x, y), b)| b != b' ' && !visited[y][x])
+        .map(|(pos, _)| pos)
+        .collect::<Vec<_>>();
+    if !disconnected.is_empty() {
+        return Err(TrackError::Disconnected(disconnected));
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Action::{Add, Stay, Sub};
+
+    #[test]
+    fn test_track() {
+        let track = "  S+=\n  - -\n  =+-\n";
+        assert_eq!(
+            linearize_track(track),
+            Ok(vec![Stay, Add, Stay, Sub, Sub, Add, Stay, Sub])
+        );
+    }
+
+    #[test]
+    fn test_crossing_track() {
+        let track = "  +-=\n  = +\nS+=-+\n- -\n=+-\n";
+        let traced = trace_track(track).unwrap();
+        assert_eq!(traced.len(), 16);
+        assert_eq!(traced[0].0, (0, 2));
+        // The crossing is gone through once going right and once going down.
+        assert_eq!(traced[2].0, (2, 2));
+        assert_eq!(traced[10].0, (2, 2));
+    }
+
+    #[test]
+    fn test_invalid_tracks() {
+        assert_eq!(linearize_track("++=\n- -\n=+-"), Err(TrackError::NoStart));
+        assert_eq!(
+            linearize_track("S+S\n- -\n=+-"),
+            Err(TrackError::MultipleStarts((0, 0), (2, 0)))
+        );
+        assert_eq!(
+            linearize_track("S+x\n- -\n=+-"),
+            Err(TrackError::InvalidCell((2, 0), 'x'))
+        );
+        assert_eq!(linearize_track("S+=\n- -\n=-=-"), Err(TrackError::Fork((2, 2))));
+        assert_eq!(linearize_track("S+=\n- -\n= -"), Err(TrackError::DeadEnd((2, 2))));
+        assert_eq!(
+            linearize_track("S+=\n- -\n=+-\n\n    +"),
+            Err(TrackError::Disconnected(vec![(4, 4)]))
+        );
+    }
+
+    #[test]
+    fn test_closed_form_matches_replay() {
+        let map = [Stay, Add, Sub, Stay, Stay, Add, Add];
+        let plans: [&[Action]; 4] = [&[Add], &[Sub, Stay], &[Add, Sub, Stay], &[Sub, Sub, Add, Stay, Add]];
+        for plan in plans {
+            for loops in [0, 1, 2, 3, 10, 35, 36, 100] {
+                assert_eq!(
+                    execute_plan(&map, plan, loops),
+                    Ok(replay(&map, plan, loops).unwrap().total),
+                    "{plan:?} for {loops} loops"
+                );
+            }
         }
+    }
 
-        x = x.checked_add_signed(dx).unwrap();
-        y = y.checked_add_signed(dy).unwrap();
+    #[test]
+    fn test_short_race_over_long_period() {
+        // The period is about 10^12 steps, but the race is over long before the end of the first one.
+        let map = (0..1_000_003).map(|i| [Stay, Add, Sub][i % 3]).collect::<Vec<_>>();
+        let plan = (0..999_983).map(|i| [Add, Stay][i % 2]).collect::<Vec<_>>();
+        assert_eq!(execute_plan(&map, &plan, 2), Ok(replay(&map, &plan, 2).unwrap().total));
+    }
+
+    #[test]
+    fn test_invalid_races() {
+        assert_eq!(execute_plan(&[], &[Add], 10), Err(RaceError::EmptyTrack));
+        assert_eq!(execute_plan(&[Stay, Add], &[], 10), Err(RaceError::EmptyPlan));
+        assert_eq!(replay(&[Stay, Add], &[], 10), Err(RaceError::EmptyPlan));
+        assert_eq!(optimize(&[Stay, Add], &[Add], (0, 0, 0), 10), Err(RaceError::EmptyPlan));
+        assert_eq!(
+            execute_plan(&[Stay, Add, Add], &[Add, Add], u64::MAX),
+            Err(RaceError::Overflow)
+        );
+        // Without any change in power the total stays small enough however long the race is.
+        let steps = i128::from(u64::MAX) * 2 + 1;
+        assert_eq!(execute_plan(&[Stay, Stay], &[Stay], u64::MAX), Ok(steps * 10));
     }
 
-    result
+    #[test]
+    fn test_plans() {
+        assert_eq!(Plans::new(5, 3, 3).count(), 9240);
+        assert_eq!(Plans::new(2, 2, 0).count(), 6);
+        assert_eq!(Plans::new(3, 0, 0).collect::<Vec<_>>(), vec![vec![Add; 3]]);
+        assert_eq!(
+            Plans::new(1, 1, 1).collect::<Vec<_>>(),
+            vec![
+                vec![Add, Sub, Stay],
+                vec![Add, Stay, Sub],
+                vec![Sub, Add, Stay],
+                vec![Sub, Stay, Add],
+                vec![Stay, Add, Sub],
+                vec![Stay, Sub, Add],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_optimize() {
+        // The track decides everything, so every plan ties and the first one wins.
+        let optimization = optimize(&[Add, Sub], &[Stay, Stay, Stay], (1, 1, 1), 10).unwrap();
+        assert_eq!(optimization.best_plan, vec![Add, Sub, Stay]);
+        assert_eq!(optimization.beating_rival, 0);
+
+        let map = [Stay, Stay, Stay];
+        let optimization = optimize(&map, &[Stay, Stay, Stay], (1, 1, 1), 10).unwrap();
+        assert_eq!(optimization.best_plan, vec![Add, Stay, Sub]);
+        assert_eq!(
+            optimization.best_score,
+            execute_plan(&map, &[Add, Stay, Sub], 10).unwrap()
+        );
+        assert_eq!(
+            optimization.beating_rival,
+            Plans::new(1, 1, 1)
+                .filter(|plan| execute_plan(&map, plan, 10).unwrap() > 310)
+                .count()
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        assert_eq!(
+            replay(&[Stay, Add], &[Sub], 2),
+            Ok(Replay {
+                powers: vec![11, 10, 11, 10, 11],
+                essence_per_loop: vec![21, 42],
+                total: 53,
+            })
+        );
+        let part1 = replay_part1(include_str!("part1_sample.txt"));
+        assert_eq!(part1[0].1.essence_per_loop.len(), 10);
+        assert_eq!(part1[0].1.total, 103);
+    }
+
+    #[test]
+    fn test_render_loop() {
+        let track = "S+=\n- -\n=+-\n";
+        let plan = [Add, Sub, Stay];
+        assert_eq!(render_loop(track, &plan, 0), Ok("S+-\n- -\n=+-\n".to_string()));
+        assert_eq!(render_loop(track, &plan, 1), Ok("S++\n- -\n-+-\n".to_string()));
+        assert_eq!(render_loop(track, &[], 0), Err(RaceError::EmptyPlan));
+        assert_eq!(
+            render_loop("S+", &plan, 0),
+            Err(RaceError::Track(TrackError::DeadEnd((1, 0))))
+        );
+    }
 }
@@ -1,43 +1,130 @@
+use std::num::NonZeroUsize;
+
 fn odd_numbers() -> impl Iterator<Item = usize> {
     (1..).map(|n| 2 * n - 1)
 }
 
-pub fn solve_part1(input: &str) -> impl std::fmt::Display {
-    let mut blocks_remaining = input.trim().parse::<usize>().unwrap();
-    for n in odd_numbers() {
-        if blocks_remaining > n {
-            blocks_remaining -= n;
-        } else {
-            let would_need = n - blocks_remaining;
-            return n * would_need;
+/// Why a shrine couldn't be built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A layer ends up with no thickness at all, and so do all the layers after it, so no number of them ever uses up
+    /// the blocks.
+    Degenerate,
+    /// Only acolytes can hollow out a shrine.
+    NoAcolytes,
+    /// The shrine is too big to count its layers or blocks.
+    Overflow,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Degenerate => write!(f, "the layers run out of thickness, so the shrine never grows"),
+            Error::NoAcolytes => write!(f, "there are no acolytes to hollow out the shrine"),
+            Error::Overflow => write!(f, "the shrine is too large to compute"),
         }
     }
+}
 
-    unreachable!()
+impl std::error::Error for Error {}
+
+/// Everything that goes into building a shrine: the blocks on hand, and the priests and acolytes deciding how thick
+/// each layer is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shrine {
+    pub blocks: usize,
+    pub priests: usize,
+    /// Without any acolytes, the priests have no say and every layer is a single block thick.
+    pub acolytes: Option<NonZeroUsize>,
 }
 
-pub fn solve_part2(input: &str) -> impl std::fmt::Display {
-    const ACOLYTES: usize = 1111;
+impl Shrine {
+    /// Part 1 only gives the number of blocks, and there are no acolytes yet.
+    pub fn part1(input: &str) -> Self {
+        Self {
+            blocks: input.trim().parse().unwrap(),
+            priests: 0,
+            acolytes: None,
+        }
+    }
 
-    let priests = input.trim().parse::<usize>().unwrap();
-    let mut blocks = 20240000;
+    /// Part 2 only gives the number of priests.
+    pub fn part2(input: &str) -> Self {
+        Self {
+            blocks: 20240000,
+            priests: input.trim().parse().unwrap(),
+            acolytes: NonZeroUsize::new(1111),
+        }
+    }
 
+    /// Part 3 only gives the number of priests.
+    pub fn part3(input: &str) -> Self {
+        Self {
+            blocks: 202400000,
+            priests: input.trim().parse().unwrap(),
+            acolytes: NonZeroUsize::new(10),
+        }
+    }
+}
+
+/// `a * b % modulus`, without overflowing.
+fn mul_mod(a: usize, b: usize, modulus: usize) -> usize {
+    (a as u128 * b as u128 % modulus as u128) as usize
+}
+
+/// Build a solid shrine, returning the number of missing blocks multiplied by the width of the shrine.
+pub fn solve_solid(shrine: &Shrine) -> Result<usize, Error> {
+    let mut blocks = shrine.blocks;
     let mut thickness = 1;
 
-    for n in odd_numbers() {
-        let n = n * thickness;
-        if blocks > n {
-            blocks -= n;
+    for width in odd_numbers() {
+        let layer = width.checked_mul(thickness).ok_or(Error::Overflow)?;
+        if blocks > layer {
+            blocks -= layer;
         } else {
-            let would_need = n - blocks;
-            return would_need * n / thickness;
+            let would_need = layer - blocks;
+            return would_need.checked_mul(width).ok_or(Error::Overflow);
         }
 
-        thickness = (thickness * priests) % ACOLYTES;
+        if let Some(acolytes) = shrine.acolytes {
+            thickness = mul_mod(thickness, shrine.priests, acolytes.get());
+            if thickness == 0 {
+                return Err(Error::Degenerate);
+            }
+        }
     }
 
     unreachable!();
 }
 
+pub fn solve_part1(input: &str) -> impl std::fmt::Display {
+    solve_solid(&Shrine::part1(input)).unwrap()
+}
+
+pub fn solve_part2(input: &str) -> impl std::fmt::Display {
+    solve_solid(&Shrine::part2(input)).unwrap()
+}
+
 mod part3;
-pub use part3::solve_part3;
+mod simulation;
+pub use part3::{solve_hollow, solve_part3};
+pub use simulation::{simulate_hollow, Column, CrossSection};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solid() {
+        let shrine = |blocks, priests, acolytes| Shrine {
+            blocks,
+            priests,
+            acolytes: NonZeroUsize::new(acolytes),
+        };
+        assert_eq!(solve_solid(&Shrine::part1("13")), Ok(21));
+        assert_eq!(solve_solid(&shrine(50, 3, 5)), Ok(27));
+        assert_eq!(solve_solid(&shrine(50, 2, 2)), Err(Error::Degenerate));
+        assert_eq!(solve_solid(&shrine(50, 0, 5)), Err(Error::Degenerate));
+        assert_eq!(solve_solid(&shrine(usize::MAX, 3, usize::MAX)), Err(Error::Overflow));
+    }
+}
@@ -1,121 +1,309 @@
-const INITIAL_BLOCKS: usize = 202400000;
-const ACOLYTES: usize = 10;
+use std::collections::HashMap;
+
+use crate::{mul_mod, Error, Shrine};
 
 pub fn solve_part3(input: &str) -> usize {
-    let priests = input.trim().parse::<usize>().unwrap();
+    solve_hollow(&Shrine::part3(input)).unwrap()
+}
 
-    // Find the cycle of thicknesses, which must exists seeing as ACOLYTES is pretty small.
-    let cycle = {
-        let mut thickness = priests % ACOLYTES + ACOLYTES;
+/// Build a shrine whose columns get hollowed out, returning the number of missing blocks.
+///
+/// Every layer after the first is at least `acolytes` blocks thick, while fewer than `acolytes` blocks are hollowed out
+/// of any column, so each layer adds more blocks than hollowing can take away. The blocks needed thus only ever grow
+/// with the number of layers, and the smallest shrine needing more than are available can be searched for.
+pub fn solve_hollow(shrine: &Shrine) -> Result<usize, Error> {
+    let &Shrine {
+        blocks,
+        priests,
+        acolytes,
+    } = shrine;
+    let acolytes = acolytes.ok_or(Error::NoAcolytes)?.get();
+    // Fewer than `acolytes` blocks are hollowed out of the center column, so once it's taller than that on top of every
+    // block available, there are certainly too many layers.
+    let layers = Layers::new(priests, acolytes, blocks.saturating_add(acolytes));
 
-        let mut cycle = vec![thickness];
-        loop {
-            let next = (thickness * priests) % ACOLYTES + ACOLYTES;
-            thickness = next;
-            if cycle.contains(&next) {
-                break;
-            }
-            cycle.push(next);
+    // Needing more blocks than fit in a `usize` certainly means needing more than are available.
+    let exceeds = |count: usize| layers.hollow_blocks(count).is_none_or(|needed| needed > blocks);
+
+    // Double the number of layers until there are too many, then binary search below that.
+    let mut left_layers = 0;
+    let mut right_layers = 1;
+    while !exceeds(right_layers) {
+        left_layers = right_layers;
+        right_layers = right_layers.checked_mul(2).ok_or(Error::Overflow)?;
+    }
+    while right_layers - left_layers > 1 {
+        let count = left_layers + (right_layers - left_layers) / 2;
+        if exceeds(count) {
+            right_layers = count;
+        } else {
+            left_layers = count;
         }
-        cycle
-    };
-
-    // Find out how much thickness each cycle adds.
-    let thickness_per_cycle = cycle.iter().sum::<usize>();
-
-    // How many blocks are needed for the first cycle?
-    let blocks_for_first_cycle = cycle
-        .iter()
-        .enumerate()
-        .map(|(i, thickness)| ((2 * 1 + 1) + i) * thickness)
-        .sum::<usize>();
-
-    // How many additional blocks are needed per cycle for each cycle? (Think of this like acceleration)
-    let additional_blocks_per_cycle_per_cycle = cycle
-        .iter()
-        .enumerate()
-        .map(|(i, thickness)| (2 * 5 + 1 + i) * thickness)
-        .sum::<usize>()
-        - blocks_for_first_cycle;
-
-    // Find the minimum number of layers needed to reach to overtake the initial blocks.
-    let layers = find_minimum_layer(&cycle, blocks_for_first_cycle, additional_blocks_per_cycle_per_cycle);
-
-    // Simulate the columns and find out how many extra blocks are needed.
-    let column_zero_height = layers / cycle.len() * thickness_per_cycle + cycle[0] + 1;
-    let heights =
-        [column_zero_height, column_zero_height - 1]
-            .into_iter()
-            .chain(
-                (0..layers - 1)
-                    .zip(cycle.iter().cycle())
-                    .scan(column_zero_height - 1, |cur_height, (_, t)| {
-                        *cur_height -= *t;
-                        Some(*cur_height)
-                    }),
-            );
-    let total_blocks = calculate_total_blocks((layers + 1) * 2 - 1, heights.clone(), priests, ACOLYTES);
-    let would_need = total_blocks - INITIAL_BLOCKS;
-    return would_need;
+    }
+
+    let needed = layers.hollow_blocks(right_layers).ok_or(Error::Overflow)?;
+    Ok(needed - blocks)
 }
 
-fn find_minimum_layer(
-    cycle: &Vec<usize>,
-    blocks_for_first_cycle: usize,
-    additional_blocks_per_cycle_per_cycle: usize,
-) -> usize {
-    let mut left_cycles = 0;
-    let mut right_cycles = 40_000;
-    let mut cycles = (left_cycles + right_cycles) / 2;
-
-    loop {
-        let blocks_needed = calculate_blocks_needed(
-            1 + cycles * cycle.len(),
-            cycle,
-            blocks_for_first_cycle,
-            additional_blocks_per_cycle_per_cycle,
+/// The thicknesses of a shrine's layers, from the top one down, as a few layers followed by a cycle repeating forever.
+///
+/// The center column's height goes from `x` to `priests * x + 1` modulo `acolytes` with every layer, and from the
+/// second layer on that's all the next thickness depends on, so the thicknesses start cycling within `acolytes + 1` layers.
+/// Adding up any number of layers then comes down to a few arithmetic series.
+#[derive(Debug, Clone)]
+struct Layers {
+    priests: usize,
+    acolytes: usize,
+    /// Every thickness up to the end of the first cycle.
+    thicknesses: Vec<usize>,
+    /// Where in `thicknesses` the cycle starts, unless the walk was cut short before getting there.
+    cycle_start: Option<usize>,
+}
+
+impl Layers {
+    /// Walk the layers until they cycle, or until the center column alone is taller than `limit`, as no shrine with
+    /// more layers than that ever needs to be built.
+    fn new(priests: usize, acolytes: usize, limit: usize) -> Self {
+        let mut thicknesses = Vec::new();
+        let mut cycle_start = None;
+        let mut first_seen = HashMap::new();
+        let mut thickness = Some(1);
+        let mut height = 0usize;
+
+        while let Some(next) = thickness {
+            if !thicknesses.is_empty() {
+                if let Some(&start) = first_seen.get(&(height % acolytes)) {
+                    cycle_start = Some(start);
+                    break;
+                }
+                first_seen.insert(height % acolytes, thicknesses.len());
+            }
+            let Some(next_height) = height.checked_add(next).filter(|&next_height| next_height <= limit) else {
+                thicknesses.push(next);
+                break;
+            };
+            thicknesses.push(next);
+            height = next_height;
+            thickness = mul_mod(next, priests, acolytes).checked_add(acolytes);
+        }
+
+        Self {
+            priests,
+            acolytes,
+            thicknesses,
+            cycle_start,
+        }
+    }
+
+    /// The blocks making up a hollow shrine with the given number of layers, or `None` if that overflows or goes past
+    /// where the walk stopped.
+    fn hollow_blocks(&self, layers: usize) -> Option<usize> {
+        let acolytes = self.acolytes;
+        let (start, cycle) = match self.cycle_start {
+            Some(start) => (start, &self.thicknesses[start..]),
+            None if layers <= self.thicknesses.len() => (layers, &[][..]),
+            None => return None,
+        };
+        let prefix = &self.thicknesses[..start.min(layers)];
+        let (cycles, rest) = match layers.checked_sub(start) {
+            Some(cycled) if !cycle.is_empty() => (cycled / cycle.len(), cycled % cycle.len()),
+            _ => (0, 0),
+        };
+
+        // The center column goes through every layer, and each column further out skips one more layer from the top,
+        // so layer `k` shows up in `k + 1` columns. Every cycle adds the same blocks, but one column further out.
+        let cycled = start.checked_add(cycles.checked_mul(cycle.len())?)?;
+        let mut outside_cycles = prefix.iter().enumerate().chain(
+            cycle[..rest]
+                .iter()
+                .enumerate()
+                .map(|(o, thickness)| (cycled + o, thickness)),
         );
-        if blocks_needed > INITIAL_BLOCKS {
-            right_cycles = cycles;
-        } else {
-            left_cycles = cycles;
+        let (mut height, mut columns_height) =
+            outside_cycles.try_fold((0usize, 0usize), |(height, columns_height), (k, &thickness)| {
+                Some((
+                    height.checked_add(thickness)?,
+                    columns_height.checked_add(k.checked_add(1)?.checked_mul(thickness)?)?,
+                ))
+            })?;
+        if cycles > 0 {
+            let cycle_height = cycle.iter().sum::<usize>();
+            let first_columns_height = cycle.iter().enumerate().try_fold(0usize, |total, (o, &thickness)| {
+                total.checked_add((start + o + 1).checked_mul(thickness)?)
+            })?;
+            let pairs = if cycles % 2 == 0 {
+                (cycles / 2).checked_mul(cycles - 1)?
+            } else {
+                cycles.checked_mul((cycles - 1) / 2)?
+            };
+            height = cycle_height.checked_mul(cycles)?.checked_add(height)?;
+            columns_height = first_columns_height
+                .checked_mul(cycles)?
+                .checked_add(cycle.len().checked_mul(cycle_height)?.checked_mul(pairs)?)?
+                .checked_add(columns_height)?;
         }
-        if right_cycles - left_cycles <= 1 {
-            break;
+
+        // Column `i` from the center is `height` minus the first `i` layers, so it has that many blocks hollowed out
+        // modulo `acolytes`, and those running heights repeat along with the thicknesses. All but the outermost columns
+        // get hollowed out, and all but the center one are mirrored on the other side.
+        let width = layers.checked_mul(2)?.checked_sub(1)?;
+        let hollowing = mul_mod(width, self.priests, acolytes);
+        let top = height % acolytes;
+        let removed = |above: usize| {
+            let column = if top >= above {
+                top - above
+            } else {
+                top + (acolytes - above)
+            };
+            mul_mod(hollowing, column, acolytes)
+        };
+        let hollowed = layers - 1;
+        let mut above = 0;
+        let mut total_removed = 0usize;
+        for &thickness in &self.thicknesses[..start.min(hollowed)] {
+            total_removed = total_removed.checked_add(removed(above))?;
+            above = add_mod(above, thickness, acolytes);
         }
-        cycles = (left_cycles + right_cycles) / 2;
+        if let Some(cycled) = hollowed.checked_sub(start).filter(|_| !cycle.is_empty()) {
+            let mut cycle_removed = 0usize;
+            let mut rest_removed = 0usize;
+            for (o, &thickness) in cycle.iter().enumerate() {
+                if o == cycled % cycle.len() {
+                    rest_removed = cycle_removed;
+                }
+                cycle_removed = cycle_removed.checked_add(removed(above))?;
+                above = add_mod(above, thickness, acolytes);
+            }
+            total_removed = cycle_removed
+                .checked_mul(cycled / cycle.len())?
+                .checked_add(rest_removed)?
+                .checked_add(total_removed)?;
+        }
+        let center_removed = if hollowed > 0 { removed(0) } else { 0 };
+
+        columns_height
+            .checked_mul(2)?
+            .checked_sub(height)?
+            .checked_sub(total_removed.checked_mul(2)?.checked_sub(center_removed)?)
     }
-    1 + cycles * cycle.len()
 }
 
-fn calculate_blocks_needed(
-    layers: usize,
-    cycle: &Vec<usize>,
-    blocks_for_first_cycle: usize,
-    additional_blocks_per_cycle_per_cycle: usize,
-) -> usize {
-    let cycles_elapsed = layers / cycle.len();
-    let blocks_needed = blocks_for_first_cycle * (cycles_elapsed + 1)
-        + additional_blocks_per_cycle_per_cycle * (1..=cycles_elapsed).sum::<usize>();
-    blocks_needed
+/// `(a + b) % modulus` for `a` already below `modulus`, without overflowing.
+fn add_mod(a: usize, b: usize, modulus: usize) -> usize {
+    let b = b % modulus;
+    if a >= modulus - b {
+        a - (modulus - b)
+    } else {
+        a + b
+    }
 }
 
-fn calculate_total_blocks(
-    width: usize,
-    mut heights: impl Iterator<Item = usize>,
-    priests: usize,
-    acolytes: usize,
-) -> usize {
-    let first = heights.next().unwrap();
-    let mut total_blocks = first;
-    total_blocks -= (width * priests * first) % acolytes;
-    heights.enumerate().for_each(|(i, height)| {
-        let removed = if i == (width + 1) / 2 - 2 {
-            0
-        } else {
-            (width * priests * height) % acolytes
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{simulate_hollow, CrossSection};
+
+    #[test]
+    fn test_simulation() {
+        let shrine = Shrine {
+            blocks: 160,
+            priests: 2,
+            acolytes: NonZeroUsize::new(5),
+        };
+        let (cross_section, missing) = simulate_hollow(&shrine).unwrap();
+        assert_eq!(missing, 2);
+        assert_eq!(cross_section.blocks(), 162);
+        assert!(format!("{cross_section}").ends_with("#########\n##.###.##\n##.#.#.##\n#..#.#..#\n#..#.#..#\n"));
+    }
+
+    #[test]
+    fn test_hollow_blocks_matches_simulation() {
+        for priests in 0..10 {
+            for acolytes in 1..10 {
+                let shrine = Shrine {
+                    blocks: 0,
+                    priests,
+                    acolytes: NonZeroUsize::new(acolytes),
+                };
+                let layers = Layers::new(priests, acolytes, usize::MAX);
+                for count in 1..40 {
+                    let cross_section = CrossSection::build(&shrine, count).unwrap();
+                    assert_eq!(layers.hollow_blocks(count), Some(cross_section.blocks()), "{shrine:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hollow_matches_simulation() {
+        for priests in 1..9 {
+            for acolytes in 1..9 {
+                for blocks in 1..400 {
+                    let shrine = Shrine {
+                        blocks,
+                        priests,
+                        acolytes: NonZeroUsize::new(acolytes),
+                    };
+                    let (_, missing) = simulate_hollow(&shrine).unwrap();
+                    assert_eq!(solve_hollow(&shrine), Ok(missing), "{shrine:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_huge_supplies() {
+        let priests = include_str!("part3.txt").trim().parse().unwrap();
+        let shrine = Shrine {
+            blocks: 250_000_000,
+            priests,
+            acolytes: NonZeroUsize::new(10),
+        };
+        assert_eq!(solve_hollow(&shrine), Ok(simulate_hollow(&shrine).unwrap().1));
+
+        let shrine = Shrine {
+            blocks: 10usize.pow(12),
+            priests: 3,
+            acolytes: NonZeroUsize::new(10),
         };
-        total_blocks += 2 * (height - removed);
-    });
-    total_blocks
+        let missing = solve_hollow(&shrine).unwrap();
+        assert!(missing < 10usize.pow(7));
+
+        // Whole cycles of layers are added up at once, so these take no longer than the small ones.
+        let shrine = Shrine {
+            blocks: 10usize.pow(15),
+            ..shrine
+        };
+        assert_eq!(solve_hollow(&shrine), Ok(242676528));
+        let shrine = Shrine {
+            blocks: 10usize.pow(18),
+            ..shrine
+        };
+        assert_eq!(solve_hollow(&shrine), Ok(7380658202));
+
+        let shrine = Shrine {
+            blocks: usize::MAX,
+            priests: usize::MAX,
+            acolytes: NonZeroUsize::new(usize::MAX),
+        };
+        assert_eq!(solve_hollow(&shrine), Err(Error::Overflow));
+
+        assert_eq!(solve_hollow(&Shrine::part1("10")), Err(Error::NoAcolytes));
+    }
+
+    #[test]
+    fn test_part3_matches_simulation() {
+        let shrine = Shrine::part3(include_str!("part3.txt"));
+        let (_, missing) = simulate_hollow(&shrine).unwrap();
+        assert_eq!(solve_hollow(&shrine), Ok(missing));
+
+        let shrine = Shrine {
+            acolytes: NonZeroUsize::new(7),
+            ..shrine
+        };
+        let (_, missing) = simulate_hollow(&shrine).unwrap();
+        assert_eq!(solve_hollow(&shrine), Ok(missing));
+    }
 }
@@ -0,0 +1,131 @@
+use std::fmt::Display;
+
+use crate::{Error, Shrine};
+
+/// One column of a hollow shrine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    /// How many blocks high the column is stacked before hollowing.
+    pub height: usize,
+    /// How many blocks are taken out from the bottom of the column.
+    pub hollowed: usize,
+}
+
+impl Column {
+    /// The blocks actually left in the column.
+    pub fn blocks(&self) -> usize {
+        self.height - self.hollowed
+    }
+}
+
+/// A hollow shrine built block by block, for checking the analytic solution on small inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossSection {
+    /// The columns from left to right.
+    pub columns: Vec<Column>,
+}
+
+impl CrossSection {
+    /// Build a shrine with the given number of layers, the first of which is a single block thick.
+    pub fn build(shrine: &Shrine, layers: usize) -> Result<Self, Error> {
+        let mut stack = Stack::new(shrine)?;
+        (0..layers).for_each(|_| stack.add_layer());
+        Ok(stack.hollow())
+    }
+
+    /// The total number of blocks the shrine is made of.
+    pub fn blocks(&self) -> usize {
+        self.columns.iter().map(Column::blocks).sum()
+    }
+}
+
+/// Renders the shrine from the side, with `#` for blocks and `.` for the hollowed out space.
+impl Display for CrossSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let top = self.columns.iter().map(|column| column.height).max().unwrap_or(0);
+        for y in (0..top).rev() {
+            for column in &self.columns {
+                let cell = if y >= column.height {
+                    ' '
+                } else if y < column.hollowed {
+                    '.'
+                } else {
+                    '#'
+                };
+                write!(f, "{cell}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Like [`solve_hollow`](crate::solve_hollow), but adding one layer at a time until the blocks run out. Returns the
+/// first shrine that can't be built along with the number of missing blocks.
+///
+/// This hollows out the whole shrine again for every layer, so it takes quadratic time in the number of layers.
+pub fn simulate_hollow(shrine: &Shrine) -> Result<(CrossSection, usize), Error> {
+    let mut stack = Stack::new(shrine)?;
+    loop {
+        stack.add_layer();
+        let cross_section = stack.hollow();
+        let blocks = cross_section.blocks();
+        if blocks > shrine.blocks {
+            return Ok((cross_section, blocks - shrine.blocks));
+        }
+    }
+}
+
+/// The column heights of a shrine that is still being stacked, before any hollowing.
+struct Stack {
+    priests: usize,
+    acolytes: usize,
+    thickness: usize,
+    /// The heights from the center column outwards.
+    heights: Vec<usize>,
+}
+
+impl Stack {
+    fn new(shrine: &Shrine) -> Result<Self, Error> {
+        Ok(Self {
+            priests: shrine.priests,
+            acolytes: shrine.acolytes.ok_or(Error::NoAcolytes)?.get(),
+            thickness: 0,
+            heights: Vec::new(),
+        })
+    }
+
+    /// Stack a layer on top of the whole shrine so far, sticking out by one column on either side.
+    fn add_layer(&mut self) {
+        self.thickness = if self.heights.is_empty() {
+            1
+        } else {
+            (self.thickness * self.priests) % self.acolytes + self.acolytes
+        };
+        self.heights.iter_mut().for_each(|height| *height += self.thickness);
+        self.heights.push(self.thickness);
+    }
+
+    /// Hollow out every column but the outermost ones.
+    fn hollow(&self) -> CrossSection {
+        // The heights are from the center outwards, so mirror them to get the full width.
+        let width = (2 * self.heights.len()).saturating_sub(1);
+        let columns = self
+            .heights
+            .iter()
+            .rev()
+            .chain(self.heights.iter().skip(1))
+            .enumerate()
+            .map(|(x, &height)| {
+                let outermost = x == 0 || x == width - 1;
+                let hollowed = if outermost {
+                    0
+                } else {
+                    (self.priests * width * height) % self.acolytes
+                };
+                Column { height, hollowed }
+            })
+            .collect();
+        CrossSection { columns }
+    }
+}
@@ -1,75 +1,451 @@
-const PART1_AVAILABLE_STAMPS: [usize; 4] = [1, 3, 5, 10];
-const PART2_AVAILABLE_STAMPS: [usize; 10] = [1, 3, 5, 10, 15, 16, 20, 24, 25, 30];
-const PART3_AVAILABLE_STAMPS: [usize; 18] = [1, 3, 5, 10, 15, 16, 20, 24, 25, 30, 37, 38, 49, 50, 74, 75, 100, 101];
+use std::{collections::BTreeMap, fmt::Display, num::ParseIntError, ops::RangeInclusive};
 
-pub fn solve_part1(input: &str) -> usize {
-    let targets = input
-        .lines()
-        .map(|line| line.parse::<usize>().unwrap())
-        .collect::<Box<[usize]>>();
+pub const PART1_AVAILABLE_STAMPS: [usize; 4] = [1, 3, 5, 10];
+pub const PART2_AVAILABLE_STAMPS: [usize; 10] = [1, 3, 5, 10, 15, 16, 20, 24, 25, 30];
+pub const PART3_AVAILABLE_STAMPS: [usize; 18] = [1, 3, 5, 10, 15, 16, 20, 24, 25, 30, 37, 38, 49, 50, 74, 75, 100, 101];
+
+/// Parse a set of stamps, separated by commas or whitespace.
+pub fn parse_stamps(input: &str) -> Result<Vec<usize>, ParseIntError> {
+    input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|stamp| !stamp.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+fn parse_targets(input: &str) -> Box<[usize]> {
+    input.lines().map(|line| line.parse::<usize>().unwrap()).collect()
+}
+
+/// Sum the fewest stamps needed for every sparkball, using the given set of stamps.
+pub fn solve(input: &str, stamps: &[usize]) -> usize {
+    let targets = parse_targets(input);
 
-    let table = solve_for_all_targets(&PART1_AVAILABLE_STAMPS, &targets);
+    let table = solve_for_all_targets(stamps, &targets);
+
+    targets.iter().map(|&target| table.count(target).unwrap()).sum()
+}
 
-    targets.iter().copied().map(|target| table[target]).sum()
+pub fn solve_part1(input: &str) -> usize {
+    solve(input, &PART1_AVAILABLE_STAMPS)
 }
 
 pub fn solve_part2(input: &str) -> usize {
-    let targets = input
-        .lines()
-        .map(|line| line.parse::<usize>().unwrap())
-        .collect::<Box<[usize]>>();
+    solve(input, &PART2_AVAILABLE_STAMPS)
+}
 
-    let table = solve_for_all_targets(&PART2_AVAILABLE_STAMPS, &targets);
+/// How each sparkball has to be split up: into `parts` sparkballs, none of which may be more than `max_difference`
+/// brighter than any other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Split {
+    pub parts: usize,
+    pub max_difference: usize,
+}
 
-    targets.iter().copied().map(|target| table[target]).sum()
+impl Split {
+    /// Part 3 splits every sparkball in two, differing by at most 100.
+    pub const PART3: Split = Split {
+        parts: 2,
+        max_difference: 100,
+    };
 }
 
-pub fn solve_part3(input: &str) -> usize {
-    let targets = input
-        .lines()
-        .map(|line| line.parse::<usize>().unwrap())
-        .collect::<Box<[usize]>>();
+/// Sum the fewest stamps needed for every sparkball once split up, using the given set of stamps.
+pub fn solve_split(input: &str, stamps: &[usize], split: Split) -> usize {
+    let targets = parse_targets(input);
 
-    let table = solve_for_all_targets(&PART3_AVAILABLE_STAMPS, &targets);
+    let table = solve_for_all_targets(stamps, &targets);
 
     targets
         .iter()
-        .copied()
-        .map(|target| {
-            (target / 2..=target / 2 + 50)
-                .map(|a| table[a] + table[target - a])
-                .min()
-                .unwrap()
+        .map(|&target| {
+            let parts = table.split(target, split).unwrap();
+            parts.iter().map(|&part| table.count(part).unwrap()).sum::<usize>()
         })
         .sum()
 }
 
+pub fn solve_part3(input: &str) -> usize {
+    solve_split(input, &PART3_AVAILABLE_STAMPS, Split::PART3)
+}
+
+/// The stamps used for a single sparkball, as `(stamp, count)` pairs from the largest stamp to the smallest.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Combination(pub Vec<(usize, usize)>);
+
+impl Combination {
+    /// The total number of stamps used.
+    pub fn count(&self) -> usize {
+        self.0.iter().map(|&(_, count)| count).sum()
+    }
+
+    /// The brightness the stamps add up to.
+    pub fn total(&self) -> usize {
+        self.0.iter().map(|&(stamp, count)| stamp * count).sum()
+    }
+}
+
+/// Formats the combination like `2×10 + 1×3`.
+impl Display for Combination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (stamp, count)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " + ")?;
+            }
+            write!(f, "{count}×{stamp}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The fewest stamps needed for every brightness up to some maximum, along with which stamps to use.
+#[derive(Debug, Clone)]
+pub struct Table {
+    min_stamps: Box<[usize]>,
+    // The last stamp used to reach each brightness, following which leads back to zero.
+    last_stamp: Box<[usize]>,
+}
+
+impl Table {
+    /// The fewest stamps adding up to exactly `target`, or `None` if no combination does.
+    pub fn count(&self, target: usize) -> Option<usize> {
+        self.min_stamps
+            .get(target)
+            .copied()
+            .filter(|&count| count != usize::MAX)
+    }
+
+    /// Split `target` up so that the parts need as few stamps as possible in total, returning the brightness of each
+    /// part from dimmest to brightest, or `None` if it can't be split that way.
+    ///
+    /// The dimmest part fixes a window of `max_difference + 1` brightnesses which all other parts must fall into, so
+    /// the best split is the best of the splits within each possible window. Finding those without starting over for
+    /// every window is done by [`Table::search_windows`].
+    pub fn split(&self, target: usize, split: Split) -> Option<Vec<usize>> {
+        let Split { parts, max_difference } = split;
+        if parts == 0 {
+            return (target == 0).then(Vec::new);
+        }
+
+        // No two parts can differ by more than the whole target, so any looser limit makes no difference.
+        let max_difference = max_difference.min(target);
+
+        // The dimmest part is at most an even share, and at least what's left when all the others are as bright as
+        // they can be. Every part must have some brightness, though.
+        let dimmest = target
+            .saturating_sub((parts - 1).saturating_mul(max_difference))
+            .div_ceil(parts)
+            .max(1);
+        let even_share = target / parts;
+        if dimmest > even_share {
+            return None;
+        }
+
+        // Two parts are just a pair adding up to the target, so it's enough to go through every such pair.
+        if parts == 2 {
+            return (dimmest..=even_share)
+                .filter_map(|low| Some((self.count(low)? + self.count(target - low)?, low)))
+                .min()
+                .map(|(_, low)| vec![low, target - low]);
+        }
+
+        // Every window contains the brightnesses from an even share up to the brightest the dimmest part allows.
+        let knapsack = self.add_brightnesses(
+            Knapsack::new(parts, target - parts * dimmest),
+            even_share..=dimmest + max_difference,
+            dimmest,
+        );
+        let mut best = None;
+        self.search_windows(dimmest..=even_share, dimmest, max_difference, knapsack, &mut best);
+        let (_, low) = best?;
+        self.split_from(target, parts, low, max_difference)
+    }
+
+    /// Find the dimmest part of the best split among `lows`, given a knapsack of every brightness that is in the window
+    /// of all of them, relative to `base`.
+    ///
+    /// The windows of the lower half of `lows` have more brightnesses in common than those of the whole range, as do
+    /// the windows of the upper half, so each half only needs to add those to a copy of the knapsack before recursing.
+    /// Every brightness is added once per level of recursion, rather than once per window.
+    fn search_windows(
+        &self,
+        lows: RangeInclusive<usize>,
+        base: usize,
+        max_difference: usize,
+        knapsack: Knapsack,
+        best: &mut Option<(usize, usize)>,
+    ) {
+        let (first, last) = (*lows.start(), *lows.end());
+        if first == last {
+            if let Some(cost) = knapsack.cheapest() {
+                if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+                    *best = Some((cost, first));
+                }
+            }
+            return;
+        }
+
+        let add = |mut knapsack: Knapsack, brightnesses: RangeInclusive<usize>| {
+            for brightness in brightnesses {
+                if let Some(cost) = self.count(brightness) {
+                    knapsack.add(brightness - base, cost);
+                }
+            }
+            knapsack
+        };
+        // The windows of `first..=last` all contain `last..=first + max_difference`.
+        let mid = first + (last - first) / 2;
+        let lower = add(knapsack.clone(), mid..=(last - 1).min(first + max_difference));
+        self.search_windows(first..=mid, base, max_difference, lower, best);
+        let upper = add(
+            knapsack,
+            last.max(first + max_difference + 1)..=mid + 1 + max_difference,
+        );
+        self.search_windows(mid + 1..=last, base, max_difference, upper, best);
+    }
+
+    /// Allow parts of every brightness in `brightnesses` that can be made at all, relative to `base`.
+    fn add_brightnesses(&self, mut knapsack: Knapsack, brightnesses: RangeInclusive<usize>, base: usize) -> Knapsack {
+        for brightness in brightnesses {
+            if let Some(cost) = self.count(brightness) {
+                knapsack.add(brightness - base, cost);
+            }
+        }
+        knapsack
+    }
+
+    /// The best split of `target` whose dimmest part is at least `low`, with the brightness of each part.
+    fn split_from(&self, target: usize, parts: usize, low: usize, max_difference: usize) -> Option<Vec<usize>> {
+        // How much brightness the parts add on top of `low` between them.
+        let extra = target - parts * low;
+        let offset_costs: Vec<_> = (0..=max_difference.min(extra)).map(|y| self.count(low + y)).collect();
+
+        // The cheapest way to reach `sum` with one more part, given the cheapest ways to reach each sum without it.
+        let add_part = |previous: &[Option<(usize, usize)>], sum: usize| {
+            (0..offset_costs.len().min(sum + 1))
+                .filter_map(|y| {
+                    let (cost, _) = (*previous.get(sum - y)?)?;
+                    Some((cost + offset_costs[y]?, y))
+                })
+                .min()
+        };
+
+        // layers[i][sum] is the cheapest cost for `i` parts to add `sum` between them, and the last part's offset.
+        let mut layers = vec![vec![Some((0, 0))]];
+        for i in 1..parts {
+            let previous = &layers[i - 1];
+            let layer = (0..=extra.min(i * max_difference))
+                .map(|sum| add_part(previous, sum))
+                .collect();
+            layers.push(layer);
+        }
+        // Only the total matters for the last part.
+        let (_, y) = add_part(&layers[parts - 1], extra)?;
+
+        let mut split = vec![low + y];
+        let mut sum = extra - y;
+        for layer in layers[1..].iter().rev() {
+            let (_, y) = layer[sum].unwrap();
+            split.push(low + y);
+            sum -= y;
+        }
+        split.sort_unstable();
+        Some(split)
+    }
+
+    /// One of the smallest combinations of stamps adding up to exactly `target`, or `None` if there is none.
+    pub fn combination(&self, mut target: usize) -> Option<Combination> {
+        self.count(target)?;
+        let mut stamps = Vec::new();
+        while target > 0 {
+            let stamp = self.last_stamp[target];
+            stamps.push(stamp);
+            target -= stamp;
+        }
+
+        stamps.sort_unstable_by(|a, b| b.cmp(a));
+        let mut combination = Combination::default();
+        for stamp in stamps {
+            match combination.0.last_mut() {
+                Some((last, count)) if *last == stamp => *count += 1,
+                _ => combination.0.push((stamp, 1)),
+            }
+        }
+        Some(combination)
+    }
+}
+
+/// The fewest stamps needed to make some number of parts add up to every total, using any brightnesses added so far
+/// as many times as needed. Brightnesses and totals are relative to some base, and totals past `extra` are left out.
+#[derive(Debug, Clone)]
+struct Knapsack {
+    parts: usize,
+    extra: usize,
+    // The cost for `i` parts to add up to `sum` is at `i * (extra + 1) + sum`.
+    costs: Vec<usize>,
+}
+
+impl Knapsack {
+    fn new(parts: usize, extra: usize) -> Self {
+        let mut costs = vec![usize::MAX; (parts + 1) * (extra + 1)];
+        costs[0] = 0;
+        Self { parts, extra, costs }
+    }
+
+    /// Allow parts of the given brightness, which need `cost` stamps each.
+    fn add(&mut self, offset: usize, cost: usize) {
+        let width = self.extra + 1;
+        // Going up in both parts and sums lets a part of this brightness be added on top of ones added just before.
+        for i in 1..=self.parts {
+            for sum in offset..width {
+                let with = self.costs[(i - 1) * width + sum - offset].saturating_add(cost);
+                let cell = &mut self.costs[i * width + sum];
+                *cell = (*cell).min(with);
+            }
+        }
+    }
+
+    /// The fewest stamps needed for all the parts to add up to exactly `extra`.
+    fn cheapest(&self) -> Option<usize> {
+        self.costs.last().copied().filter(|&cost| cost != usize::MAX)
+    }
+}
+
+/// Find which stamps to use for every sparkball, or `None` for those that can't be made with the given stamps.
+pub fn combinations(input: &str, stamps: &[usize]) -> Vec<(usize, Option<Combination>)> {
+    let targets = parse_targets(input);
+
+    let table = solve_for_all_targets(stamps, &targets);
+
+    targets
+        .iter()
+        .map(|&target| (target, table.combination(target)))
+        .collect()
+}
+
+/// The fewest stamps adding up to exactly `target` when only so many of each are available, given as
+/// `(stamp, count)` pairs, or `None` if the supply can't make it.
+///
+/// This is a bounded knapsack, turned into a 0/1 knapsack by binary splitting: `count` copies of a stamp become bundles
+/// of 1, 2, 4, ... copies plus whatever is left over, so that any number of copies up to `count` is made of distinct
+/// bundles.
+pub fn bounded_combination(supply: &[(usize, usize)], target: usize) -> Option<Combination> {
+    let bundles: Vec<(usize, usize)> = supply
+        .iter()
+        .filter(|&&(stamp, _)| stamp > 0)
+        .flat_map(|&(stamp, count)| {
+            let mut left = count;
+            let mut size = 1usize;
+            std::iter::from_fn(move || {
+                let copies = size.min(left);
+                left -= copies;
+                size = size.saturating_mul(2);
+                (copies > 0).then_some((stamp, copies))
+            })
+        })
+        .collect();
+
+    let mut min_stamps = vec![usize::MAX; 1 + target];
+    min_stamps[0] = 0;
+    // Whether each bundle is part of the best way to reach each target, once the bundles before it are considered.
+    let mut taken = vec![vec![false; 1 + target]; bundles.len()];
+    for (i, &(stamp, copies)) in bundles.iter().enumerate() {
+        let Some(value) = stamp.checked_mul(copies).filter(|&value| value <= target) else {
+            continue;
+        };
+        // Going down makes sure each bundle is used at most once.
+        for sum in (value..=target).rev() {
+            let count = min_stamps[sum - value].saturating_add(copies);
+            if count < min_stamps[sum] {
+                min_stamps[sum] = count;
+                taken[i][sum] = true;
+            }
+        }
+    }
+    if min_stamps[target] == usize::MAX {
+        return None;
+    }
+
+    let mut used = BTreeMap::new();
+    let mut sum = target;
+    for (i, &(stamp, copies)) in bundles.iter().enumerate().rev() {
+        if taken[i][sum] {
+            *used.entry(stamp).or_insert(0) += copies;
+            sum -= stamp * copies;
+        }
+    }
+    Some(Combination(used.into_iter().rev().collect()))
+}
+
+/// The outcome of stamping sparkballs one after the other from a shared supply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Batch {
+    /// Every sparkball with the stamps it used, or `None` if there weren't enough stamps left for it.
+    pub sparkballs: Vec<(usize, Option<Combination>)>,
+    /// The stamps left over afterwards, as `(stamp, count)` pairs.
+    pub remaining: Vec<(usize, usize)>,
+}
+
+impl Batch {
+    /// The sparkballs that couldn't be made, in order.
+    pub fn impossible(&self) -> impl Iterator<Item = usize> + '_ {
+        self.sparkballs
+            .iter()
+            .filter(|(_, combination)| combination.is_none())
+            .map(|&(target, _)| target)
+    }
+}
+
+/// Stamp every sparkball in order, each using as few as possible of the stamps the previous ones left over.
+/// Sparkballs which can't be made are skipped without using up any stamps.
+pub fn solve_with_supply(input: &str, supply: &[(usize, usize)]) -> Batch {
+    let mut remaining = supply.to_vec();
+    let sparkballs = parse_targets(input)
+        .iter()
+        .map(|&target| {
+            let combination = bounded_combination(&remaining, target);
+            for &(stamp, mut count) in combination.iter().flat_map(|combination| &combination.0) {
+                for (_, available) in remaining.iter_mut().filter(|(supplied, _)| *supplied == stamp) {
+                    let used = count.min(*available);
+                    *available -= used;
+                    count -= used;
+                }
+            }
+            (target, combination)
+        })
+        .collect();
+
+    Batch { sparkballs, remaining }
+}
+
 // adapted from
 // https://github.com/maneatingape/everybody-codes-rust/blob/090c126651fc5c4dfde4af07e4dd304ff4928b5b/src/event2024/quest09.rs#L21
-fn solve_for_all_targets(stamps: &[usize], targets: &[usize]) -> Box<[usize]> {
+pub fn solve_for_all_targets(stamps: &[usize], targets: &[usize]) -> Table {
     // Compute the maximum target value.
-    let max_target = targets.iter().copied().max().unwrap();
+    let max_target = targets.iter().copied().max().unwrap_or(0);
 
-    // Create a dynamic programming table for each target value.
+    // Create a dynamic programming table for each target value, where nothing at all is needed for a target of zero.
     let mut min_stamps = vec![usize::MAX; 1 + max_target].into_boxed_slice();
+    let mut last_stamp = vec![0; 1 + max_target].into_boxed_slice();
+    min_stamps[0] = 0;
 
-    // Each stamp can be used to make a target value of itself.
-    for &stamp in stamps {
-        min_stamps[stamp] = 1;
-    }
-
-    // Let's consider every target value from 2 to the maximum.
-    for target in 2..=max_target {
+    // Let's consider every target value from 1 to the maximum.
+    for target in 1..=max_target {
         for &stamp in stamps {
             // We'll update the table for each target value, comparing the current best way to get our desired target
-            // with the new way to get it using the current stamp.
-            if target > stamp {
-                min_stamps[target] = min_stamps[target].min(min_stamps[target - stamp].saturating_add(1));
+            // with the new way to get it using the current stamp, remembering the stamp if it's better.
+            if stamp == 0 || stamp > target {
+                continue;
+            }
+            let count = min_stamps[target - stamp].saturating_add(1);
+            if count < min_stamps[target] {
+                min_stamps[target] = count;
+                last_stamp[target] = stamp;
             }
         }
     }
 
-    min_stamps
+    Table { min_stamps, last_stamp }
 }
 
 #[cfg(test)]
@@ -85,4 +461,78 @@ mod tests {
     fn test_part3() {
         assert_eq!(solve_part3("156488\n352486\n546212"), 10_449)
     }
+
+    #[test]
+    fn test_split() {
+        let table = solve_for_all_targets(&PART3_AVAILABLE_STAMPS, &[156488]);
+        let split = table.split(156488, Split::PART3).unwrap();
+        assert_eq!(split.iter().sum::<usize>(), 156488);
+        assert!(split[1] - split[0] <= 100);
+
+        let table = solve_for_all_targets(&PART1_AVAILABLE_STAMPS, &[30]);
+        let split = |parts, max_difference| table.split(30, Split { parts, max_difference });
+        assert_eq!(split(3, 0), Some(vec![10, 10, 10]));
+        assert_eq!(split(2, 20), Some(vec![10, 20]));
+        assert_eq!(split(4, 1), Some(vec![7, 7, 8, 8]));
+        assert_eq!(split(4, 0), None);
+        assert_eq!(split(31, 30), None);
+        assert_eq!(split(3, usize::MAX / 2), Some(vec![10, 10, 10]));
+        assert_eq!(split(3, usize::MAX), Some(vec![10, 10, 10]));
+        assert_eq!(split(2, usize::MAX), Some(vec![10, 20]));
+    }
+
+    #[test]
+    fn test_split_matches_brute_force() {
+        let table = solve_for_all_targets(&PART3_AVAILABLE_STAMPS, &[150]);
+        let count = |brightness| table.count(brightness).unwrap();
+        for target in 3..=150 {
+            for max_difference in 0..12 {
+                let brute_force = (1..=target)
+                    .flat_map(|a| (a..=target).map(move |b| (a, b)))
+                    .filter(|&(a, b)| a + b < target && target - a - b >= b)
+                    .filter(|&(a, b)| target - a - b - a <= max_difference)
+                    .map(|(a, b)| count(a) + count(b) + count(target - a - b))
+                    .min();
+                let split = table.split(
+                    target,
+                    Split {
+                        parts: 3,
+                        max_difference,
+                    },
+                );
+                assert_eq!(split.map(|split| split.into_iter().map(count).sum()), brute_force);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        assert_eq!(
+            bounded_combination(&[(10, 1), (1, 20)], 25),
+            Some(Combination(vec![(10, 1), (1, 15)]))
+        );
+        assert_eq!(bounded_combination(&[(10, 1), (1, 5)], 25), None);
+
+        let batch = solve_with_supply("16\n16\n12", &[(10, 2), (5, 1), (1, 3)]);
+        assert_eq!(batch.impossible().collect::<Vec<_>>(), [16]);
+        assert_eq!(batch.sparkballs[0].1, Some(Combination(vec![(10, 1), (5, 1), (1, 1)])));
+        assert_eq!(batch.sparkballs[2].1, Some(Combination(vec![(10, 1), (1, 2)])));
+        assert_eq!(batch.remaining, [(10, 0), (5, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn test_combinations() {
+        let stamps = parse_stamps("1, 3, 5, 10").unwrap();
+        let combinations = combinations("2\n4\n7\n16", &stamps);
+        let shown: Vec<_> = combinations
+            .iter()
+            .map(|(_, combination)| combination.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(shown, ["2×1", "1×3 + 1×1", "1×5 + 2×1", "1×10 + 1×5 + 1×1"]);
+        for (target, combination) in combinations {
+            assert_eq!(combination.unwrap().total(), target);
+        }
+        assert_eq!(solve("2\n4\n7\n16", &stamps), 10);
+        assert_eq!(solve_for_all_targets(&[3, 5], &[7]).combination(7), None);
+    }
 }
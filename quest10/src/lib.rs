@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{collections::VecDeque, fmt::Display};
 
 use grid::Grid;
 use itertools::iproduct;
@@ -9,19 +9,133 @@ const PATTERN_COORDS: [usize; 4] = [0, 1, 6, 7];
 const UNKNOWN: u8 = b'?';
 const EMPTY: u8 = b'.';
 
+/// Why a tile can't be completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contradiction {
+    /// A rune doesn't appear among the runes of its row or of its column.
+    Mismatch,
+    /// A rune appears more than once in the same row or column.
+    Repeated,
+    /// The runes of a row and a column are all known, yet they have none in common.
+    NoCandidate,
+}
+
+impl Display for Contradiction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Contradiction::Mismatch => write!(f, "a rune doesn't belong to its row or column"),
+            Contradiction::Repeated => write!(f, "a rune is repeated within a row or column"),
+            Contradiction::NoCandidate => write!(f, "no rune is shared by a row and a column"),
+        }
+    }
+}
+
+/// How far deducing the runes of a tile got. Cells are given as positions in the whole wall.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TileStatus {
+    /// Every rune is known, giving the runic word.
+    Solved { word: String },
+    /// Nothing is wrong with the tile, but these runes couldn't be deduced.
+    Ambiguous { cells: Vec<(usize, usize)> },
+    /// The tile can't be completed, because of these runes.
+    Contradictory {
+        reason: Contradiction,
+        cells: Vec<(usize, usize)>,
+    },
+}
+
+impl TileStatus {
+    /// The cells to point out when the tile isn't solved.
+    pub fn offending_cells(&self) -> &[(usize, usize)] {
+        match self {
+            TileStatus::Solved { .. } => &[],
+            TileStatus::Ambiguous { cells } | TileStatus::Contradictory { cells, .. } => cells,
+        }
+    }
+}
+
+impl Display for TileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TileStatus::Solved { word } => write!(f, "solved: {word}"),
+            TileStatus::Ambiguous { cells } => write!(f, "ambiguous: {} runes can't be deduced", cells.len()),
+            TileStatus::Contradictory { reason, cells } => {
+                write!(f, "contradictory: {reason}, at")?;
+                for (y, x) in cells {
+                    write!(f, " ({y}, {x})")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Check how complete the tile at `(base_y, base_x)` is.
+pub fn tile_status(grid: &Grid<u8>, (base_y, base_x): (usize, usize)) -> TileStatus {
+    let at = |y: usize, x: usize| (base_y + y, base_x + x);
+    let mut mismatched = Vec::new();
+    let mut repeated = Vec::new();
+    let mut stuck = Vec::new();
+    let mut empty = Vec::new();
+
+    for (y, x) in iproduct!(2..6, 2..6) {
+        let row_choices = PATTERN_COORDS.map(|x| grid[at(y, x)]);
+        let col_choices = PATTERN_COORDS.map(|y| grid[at(y, x)]);
+        match grid[at(y, x)] {
+            EMPTY => {
+                let all_known = !row_choices.contains(&UNKNOWN) && !col_choices.contains(&UNKNOWN);
+                if all_known && !row_choices.iter().any(|b| col_choices.contains(b)) {
+                    stuck.push(at(y, x));
+                } else {
+                    empty.push(at(y, x));
+                }
+            }
+            rune => {
+                let fits = |choices: [u8; 4]| choices.contains(&rune) || choices.contains(&UNKNOWN);
+                if !fits(row_choices) || !fits(col_choices) {
+                    mismatched.push(at(y, x));
+                }
+                let in_row = (2..6).filter(|&x| grid[at(y, x)] == rune).count();
+                let in_col = (2..6).filter(|&y| grid[at(y, x)] == rune).count();
+                if in_row > 1 || in_col > 1 {
+                    repeated.push(at(y, x));
+                }
+            }
+        }
+    }
+
+    let contradictions = [
+        (Contradiction::Mismatch, mismatched),
+        (Contradiction::Repeated, repeated),
+        (Contradiction::NoCandidate, stuck),
+    ];
+    if let Some((reason, cells)) = contradictions.into_iter().find(|(_, cells)| !cells.is_empty()) {
+        return TileStatus::Contradictory { reason, cells };
+    }
+    if !empty.is_empty() {
+        return TileStatus::Ambiguous { cells: empty };
+    }
+    let word = iproduct!(2..6, 2..6).map(|(y, x)| char::from(grid[at(y, x)])).collect();
+    TileStatus::Solved { word }
+}
+
+/// Deduce as much of a single tile as possible on its own.
+fn solve_tile(grid: &mut Grid<u8>, origin: (usize, usize)) -> TileStatus {
+    let mut changed = Vec::new();
+    while deduce(grid, origin, &mut changed) {}
+    tile_status(grid, origin)
+}
+
+/// Returns the runic word, with a `.` for every rune which couldn't be deduced.
 pub fn solve_part1(input: &str) -> impl Display {
-    let grid = Grid::from_vec(
+    let mut grid = Grid::from_vec(
         input.bytes().filter(|b| !b.is_ascii_whitespace()).collect::<Vec<_>>(),
         GRID_SIDE,
     );
-    let mut result = String::with_capacity(6 * 6);
-    iproduct!(2..6, 2..6).for_each(|(y, x)| {
-        let row_choices = PATTERN_COORDS.map(|x| grid[(y, x)]);
-        let col_choices = PATTERN_COORDS.map(|y| grid[(y, x)]);
-        let choice = row_choices.into_iter().find(|&b| col_choices.contains(&b)).unwrap();
-        result.push(choice as char);
-    });
-    result
+    solve_tile(&mut grid, (0, 0));
+    iproduct!(2..6, 2..6)
+        .map(|(y, x)| char::from(grid[(y, x)]))
+        .collect::<String>()
 }
 
 fn power(s: impl IntoIterator<Item = u8>) -> usize {
@@ -31,8 +145,9 @@ fn power(s: impl IntoIterator<Item = u8>) -> usize {
         .sum()
 }
 
+/// Only solved tiles add to the total power.
 pub fn solve_part2(input: &str) -> impl Display {
-    let grid: Grid<u8> = input
+    let mut grid: Grid<u8> = input
         .lines()
         .map(|line| line.bytes().collect::<Vec<_>>())
         .collect::<Vec<_>>()
@@ -42,13 +157,10 @@ pub fn solve_part2(input: &str) -> impl Display {
 
     let mut result = 0;
 
-    for (base_y, base_x) in iproduct!((0..h).step_by(GRID_SIDE), (0..w).step_by(GRID_SIDE + 1)) {
-        result += power(iproduct!(2..6, 2..6).map(|(y, x)| {
-            let row_choices = PATTERN_COORDS.map(|x| grid[(base_y + y, base_x + x)]);
-            let col_choices = PATTERN_COORDS.map(|y| grid[(base_y + y, base_x + x)]);
-            let choice = row_choices.into_iter().find(|&b| col_choices.contains(&b)).unwrap();
-            choice
-        }));
+    for origin in iproduct!((0..h).step_by(GRID_SIDE), (0..w).step_by(GRID_SIDE + 1)) {
+        if let TileStatus::Solved { word } = solve_tile(&mut grid, origin) {
+            result += power(word.bytes());
+        }
     }
 
     result
@@ -63,124 +175,227 @@ fn only_one<T>(mut iter: impl Iterator<Item = T>) -> Option<T> {
     None
 }
 
-pub fn solve_part3(input: &str) -> impl Display {
-    let mut grid: Grid<u8> = input
-        .lines()
-        .map(|line| line.bytes().collect::<Vec<_>>())
-        .collect::<Vec<_>>()
-        .into();
-    let w = grid.cols();
-    let h = grid.rows();
+/// How far apart neighboring tiles of the wall in part 3 are, seeing as they share their two outermost rows and columns.
+const TILE_STEP: usize = GRID_SIDE - 2;
+
+/// A wall of tiles overlapping on their borders, after deducing as many runes as possible.
+#[derive(Debug, Clone)]
+pub struct Wall {
+    pub grid: Grid<u8>,
+    /// How many passes of deduction made progress on each tile, indexed by tile rather than by rune.
+    pub rounds: Grid<usize>,
+}
 
+impl Wall {
+    /// The position of the top left rune of a tile.
+    fn origin((tile_y, tile_x): (usize, usize)) -> (usize, usize) {
+        (tile_y * TILE_STEP, tile_x * TILE_STEP)
+    }
+
+    /// How far deducing the runes of every tile got, indexed by tile.
+    pub fn statuses(&self) -> Grid<TileStatus> {
+        let statuses = iproduct!(0..self.rounds.rows(), 0..self.rounds.cols())
+            .map(|tile| tile_status(&self.grid, Wall::origin(tile)))
+            .collect();
+        Grid::from_vec(statuses, self.rounds.cols())
+    }
+
+    /// The tiles a rune belongs to: just one for the center of a tile, but up to four on the borders.
+    fn tiles_containing(&self, (y, x): (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let around = |i: usize| {
+            [
+                Some(i / TILE_STEP),
+                (i / TILE_STEP).checked_sub(1).filter(|_| i % TILE_STEP < 2),
+            ]
+        };
+        iproduct!(around(y).into_iter().flatten(), around(x).into_iter().flatten())
+            .filter(|&(tile_y, tile_x)| tile_y < self.rounds.rows() && tile_x < self.rounds.cols())
+    }
+}
+
+/// Fill in a rune, keeping track of which ones changed.
+fn fill(grid: &mut Grid<u8>, changed: &mut Vec<(usize, usize)>, pos: (usize, usize), rune: u8) {
+    if grid[pos] != rune {
+        grid[pos] = rune;
+        changed.push(pos);
+    }
+}
+
+/// Make a single pass of deduction over the tile at `(base_y, base_x)`, returning whether it made any progress.
+fn deduce(grid: &mut Grid<u8>, (base_y, base_x): (usize, usize), changed: &mut Vec<(usize, usize)>) -> bool {
+    let changes = changed.len();
     let mut empties = Vec::with_capacity(4);
     let mut used = Vec::with_capacity(4);
 
-    // Run twice to ensure all patterns are deduced.
-    for (_, base_y, base_x) in iproduct!(0..2, (0..h).step_by(6).take(h / 6), (0..w).step_by(6).take(w / 6)) {
-        // Solve grid using the known patterns.
-        for y in 2..6 {
-            for x in 2..6 {
-                let row_choices = PATTERN_COORDS.map(|x| grid[(base_y + y, base_x + x)]);
-                let col_choices = PATTERN_COORDS.map(|y| grid[(base_y + y, base_x + x)]);
-
-                if let Some(choice) = row_choices
-                    .into_iter()
-                    .filter(|&b| b != UNKNOWN)
-                    .find(|&b| col_choices.contains(&b))
-                {
-                    grid[(base_y + y, base_x + x)] = choice;
-                }
-            }
-        }
-
-        // Check for deducible patterns in columns.
+    // Solve grid using the known patterns.
+    for y in 2..6 {
         for x in 2..6 {
-            // Find empty spots and used patterns in the column.
-            empties.clear();
-            used.clear();
-            for y in 2..6 {
-                match grid[(base_y + y, base_x + x)] {
-                    EMPTY => empties.push(y),
-                    b => used.push(b),
-                }
-            }
-            let [y] = empties[..] else {
+            if grid[(base_y + y, base_x + x)] != EMPTY {
                 continue;
-            };
-
-            // Check for unused known patterns.
+            }
+            let row_choices = PATTERN_COORDS.map(|x| grid[(base_y + y, base_x + x)]);
             let col_choices = PATTERN_COORDS.map(|y| grid[(base_y + y, base_x + x)]);
-            let leftovers = col_choices
+
+            if let Some(choice) = row_choices
                 .into_iter()
                 .filter(|&b| b != UNKNOWN)
-                .filter(|b| !used.contains(b));
-
-            // When there's only one pattern left, fill it in.
-            if let Some(leftover) = only_one(leftovers) {
-                // We can also deduce that an unknown in the corresponding row must match the pattern we're
-                // filling in.
-                if let Some(&x) = PATTERN_COORDS
-                    .iter()
-                    .find(|&&x| grid[(base_y + y, base_x + x)] == UNKNOWN)
-                {
-                    grid[(base_y + y, base_x + x)] = leftover;
-                }
-
-                grid[(base_y + y, base_x + x)] = leftover;
+                .find(|&b| col_choices.contains(&b))
+            {
+                fill(grid, changed, (base_y + y, base_x + x), choice);
             }
         }
+    }
 
-        // Check for deducible patterns in rows, similar to the column check.
+    // Check for deducible patterns in columns.
+    for x in 2..6 {
+        // Find empty spots and used patterns in the column.
+        empties.clear();
+        used.clear();
         for y in 2..6 {
-            empties.clear();
-            used.clear();
-
-            for x in 2..6 {
-                match grid[(base_y + y, base_x + x)] {
-                    EMPTY => empties.push(x),
-                    b => used.push(b),
-                }
+            match grid[(base_y + y, base_x + x)] {
+                EMPTY => empties.push(y),
+                b => used.push(b),
             }
-            let [x] = empties[..] else {
-                continue;
-            };
+        }
+        let [y] = empties[..] else {
+            continue;
+        };
 
-            let row_choices = PATTERN_COORDS.map(|x| grid[(base_y + y, base_x + x)]);
-            let leftovers = row_choices
-                .into_iter()
-                .filter(|&b| b != UNKNOWN)
-                .filter(|b| !used.contains(b));
-
-            if let Some(leftover) = only_one(leftovers) {
-                if let Some(&y) = PATTERN_COORDS
-                    .iter()
-                    .find(|&&y| grid[(base_y + y, base_x + x)] == UNKNOWN)
-                {
-                    grid[(base_y + y, base_x + x)] = leftover;
-                }
+        // Check for unused known patterns.
+        let col_choices = PATTERN_COORDS.map(|y| grid[(base_y + y, base_x + x)]);
+        let leftovers = col_choices
+            .into_iter()
+            .filter(|&b| b != UNKNOWN)
+            .filter(|b| !used.contains(b));
 
-                grid[(base_y + y, base_x + x)] = leftover;
+        // When there's only one pattern left, fill it in.
+        if let Some(leftover) = only_one(leftovers) {
+            // We can also deduce that an unknown in the corresponding row must match the pattern we're filling in.
+            if let Some(&x) = PATTERN_COORDS
+                .iter()
+                .find(|&&x| grid[(base_y + y, base_x + x)] == UNKNOWN)
+            {
+                fill(grid, changed, (base_y + y, base_x + x), leftover);
             }
+
+            fill(grid, changed, (base_y + y, base_x + x), leftover);
         }
     }
 
-    // Compute total power!
-    let mut result = 0;
-    let mut word = Vec::with_capacity(6 * 6);
-    'grid_loop: for (base_y, base_x) in iproduct!((0..h).step_by(6).take(h / 6), (0..w).step_by(6).take(w / 6)) {
-        word.clear();
+    // Check for deducible patterns in rows, similar to the column check.
+    for y in 2..6 {
+        empties.clear();
+        used.clear();
 
-        for (y, x) in iproduct!(2..6, 2..6) {
+        for x in 2..6 {
             match grid[(base_y + y, base_x + x)] {
-                EMPTY => continue 'grid_loop,
-                b => word.push(b),
+                EMPTY => empties.push(x),
+                b => used.push(b),
             }
         }
+        let [x] = empties[..] else {
+            continue;
+        };
+
+        let row_choices = PATTERN_COORDS.map(|x| grid[(base_y + y, base_x + x)]);
+        let leftovers = row_choices
+            .into_iter()
+            .filter(|&b| b != UNKNOWN)
+            .filter(|b| !used.contains(b));
+
+        if let Some(leftover) = only_one(leftovers) {
+            if let Some(&y) = PATTERN_COORDS
+                .iter()
+                .find(|&&y| grid[(base_y + y, base_x + x)] == UNKNOWN)
+            {
+                fill(grid, changed, (base_y + y, base_x + x), leftover);
+            }
 
-        result += power(word.drain(..));
+            fill(grid, changed, (base_y + y, base_x + x), leftover);
+        }
     }
 
-    result
+    changed.len() > changes
+}
+
+/// Deduce every rune of the wall that can be deduced.
+///
+/// Each tile is worked on until it stops making progress. Since neighboring tiles share their borders, filling in an
+/// unknown rune there can let another tile make progress, so the tiles sharing it are queued up again, until no tile
+/// changes anymore.
+pub fn solve_wall(input: &str) -> Wall {
+    let grid: Grid<u8> = input
+        .lines()
+        .map(|line| line.bytes().collect::<Vec<_>>())
+        .collect::<Vec<_>>()
+        .into();
+    let tiles_high = grid.rows().saturating_sub(2) / TILE_STEP;
+    let tiles_wide = grid.cols().saturating_sub(2) / TILE_STEP;
+    let mut wall = Wall {
+        grid,
+        rounds: Grid::new(tiles_high, tiles_wide),
+    };
+
+    let mut queue: VecDeque<_> = iproduct!(0..tiles_high, 0..tiles_wide).collect();
+    let mut queued = Grid::init(tiles_high, tiles_wide, true);
+    let mut changed = Vec::new();
+    while let Some(tile) = queue.pop_front() {
+        queued[tile] = false;
+        changed.clear();
+        while deduce(&mut wall.grid, Wall::origin(tile), &mut changed) {
+            wall.rounds[tile] += 1;
+        }
+
+        for &pos in &changed {
+            let others: Vec<_> = wall.tiles_containing(pos).filter(|&other| other != tile).collect();
+            for other in others {
+                if !queued[other] {
+                    queued[other] = true;
+                    queue.push_back(other);
+                }
+            }
+        }
+    }
+
+    wall
+}
+
+/// Renders the wall with the runes of unsolved tiles in lowercase. Runes which can't be deduced are left as `.`, while
+/// the ones making a tile contradictory are replaced with `!`.
+impl Display for Wall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut grid = self.grid.clone();
+        for (tile, status) in self.statuses().indexed_iter() {
+            let (base_y, base_x) = Wall::origin(tile);
+            for (y, x) in iproduct!(2..6, 2..6) {
+                let rune = &mut grid[(base_y + y, base_x + x)];
+                match status {
+                    TileStatus::Solved { .. } => {}
+                    TileStatus::Ambiguous { .. } => rune.make_ascii_lowercase(),
+                    TileStatus::Contradictory { cells, .. } if cells.contains(&(base_y + y, base_x + x)) => {
+                        *rune = b'!'
+                    }
+                    TileStatus::Contradictory { .. } => rune.make_ascii_lowercase(),
+                }
+            }
+        }
+        for row in grid.iter_rows() {
+            writeln!(f, "{}", row.map(|&b| char::from(b)).collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+/// Only solved tiles add to the total power.
+pub fn solve_part3(input: &str) -> impl Display {
+    solve_wall(input)
+        .statuses()
+        .iter()
+        .map(|status| match status {
+            TileStatus::Solved { word } => power(word.bytes()),
+            _ => 0,
+        })
+        .sum::<usize>()
 }
 
 #[cfg(test)]
@@ -201,4 +416,83 @@ mod tests {
     fn test_part3() {
         assert_eq!(solve_part3(include_str!("part3.txt")).to_string(), "212032");
     }
+
+    #[test]
+    fn test_statuses() {
+        let mut grid = Grid::from_vec(
+            include_str!("part1_sample.txt")
+                .bytes()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect(),
+            GRID_SIDE,
+        );
+        assert_eq!(
+            solve_tile(&mut grid, (0, 0)),
+            TileStatus::Solved {
+                word: "PTBVRCZHFLJWGMNS".to_string()
+            }
+        );
+
+        // Without the border runes of the middle rows, nothing can be said about their runes.
+        let mut grid = Grid::from_vec(
+            include_str!("part1_sample.txt")
+                .replace("CR", "??")
+                .replace("HZ", "??")
+                .replace("FL", "??")
+                .replace("JW", "??")
+                .bytes()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect(),
+            GRID_SIDE,
+        );
+        assert_eq!(
+            solve_tile(&mut grid, (0, 0)),
+            TileStatus::Ambiguous {
+                cells: vec![(3, 2), (3, 3), (3, 4), (3, 5), (4, 2), (4, 3), (4, 4), (4, 5)]
+            }
+        );
+
+        let mut grid = Grid::from_vec(
+            include_str!("part1_sample.txt")
+                .replace("CR", "XY")
+                .bytes()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect(),
+            GRID_SIDE,
+        );
+        grid[(3, 2)] = b'Z';
+        let status = solve_tile(&mut grid, (0, 0));
+        assert!(matches!(
+            status,
+            TileStatus::Contradictory {
+                reason: Contradiction::Mismatch,
+                ..
+            }
+        ));
+        assert!(status.offending_cells().contains(&(3, 2)));
+    }
+
+    #[test]
+    fn test_wall_rendering() {
+        let wall = solve_wall(include_str!("part3_sample.txt"));
+        let statuses = wall.statuses();
+        assert!(matches!(statuses[(0, 0)], TileStatus::Solved { .. }));
+        assert!(matches!(statuses[(0, 1)], TileStatus::Solved { .. }));
+        assert_eq!(
+            statuses[(1, 0)],
+            TileStatus::Contradictory {
+                reason: Contradiction::Mismatch,
+                cells: vec![(11, 2)]
+            }
+        );
+
+        let rendered = wall.to_string();
+        assert_eq!(rendered.lines().nth(2), Some("VGLWGVWLDQWLDQ"));
+        assert_eq!(rendered.lines().nth(11), Some("TJ!jtkYK.k!lLP"));
+    }
+
+    #[test]
+    fn test_part3_sample() {
+        assert_eq!(solve_part3(include_str!("part3_sample.txt")).to_string(), "3889");
+    }
 }